@@ -0,0 +1,221 @@
+//! Checks every `*.item.ron` the same way the game loads and spawns them,
+//! and that each one survives a round-trip through the item serializer.
+//!
+//! Usage: `cargo run --bin validate-items [assets dir]`
+
+use bevy::{prelude::*, reflect::TypeRegistry};
+use lifer::{
+    loading::ItemDatabase,
+    mechanics::item::{
+        item_component, item_registration, ComponentsDeserializer, ItemIcon, ItemPlugin, ItemScene,
+        ItemTemplate,
+    },
+};
+use ron::error::Position;
+use serde::de::DeserializeSeed;
+use std::{
+    any::TypeId,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+fn main() -> ExitCode {
+    let root = std::env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("assets"));
+
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        AssetPlugin::default(),
+        TransformPlugin,
+        HierarchyPlugin,
+        ItemPlugin,
+    ));
+
+    let registry = app.world.resource::<AppTypeRegistry>().read();
+
+    let mut files = Vec::new();
+    if let Err(err) = find_items(&root.join(ItemDatabase::FOLDER), &mut files) {
+        eprintln!("{}: {}", root.display(), err);
+        return ExitCode::FAILURE;
+    }
+    files.sort();
+
+    let mut errors = 0;
+    for file in &files {
+        for Diagnostic { position, message } in validate(&registry, &root, file) {
+            eprintln!(
+                "{}:{}:{}: {}",
+                file.display(),
+                position.line,
+                position.col,
+                message
+            );
+            errors += 1;
+        }
+    }
+
+    if errors > 0 {
+        eprintln!("{} error(s) in {} item file(s)", errors, files.len());
+        ExitCode::FAILURE
+    } else {
+        println!("{} item file(s) ok", files.len());
+        ExitCode::SUCCESS
+    }
+}
+
+fn find_items(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_items(&path, files)?;
+        } else if path.to_string_lossy().ends_with(ItemDatabase::EXTENSION) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+struct Diagnostic {
+    position: Position,
+    message: String,
+}
+
+impl Diagnostic {
+    fn new(position: Position, message: impl ToString) -> Self {
+        Self {
+            position,
+            message: message.to_string(),
+        }
+    }
+}
+
+fn validate(registry: &TypeRegistry, root: &Path, file: &Path) -> Vec<Diagnostic> {
+    let start = Position { line: 1, col: 1 };
+
+    let text = match std::fs::read_to_string(file) {
+        Ok(text) => text,
+        Err(err) => return vec![Diagnostic::new(start, err)],
+    };
+
+    let template = match parse(registry, &text) {
+        Ok(template) => template,
+        Err(err) => return vec![Diagnostic::new(err.position, err.code)],
+    };
+
+    let mut diagnostics = Vec::new();
+
+    for reflect in &template.components {
+        let result = item_registration(registry, &**reflect).and_then(item_component);
+        if let Err(err) = result {
+            let position = position_of(&text, reflect.reflect_type_path()).unwrap_or(start);
+            diagnostics.push(Diagnostic::new(position, err));
+        }
+    }
+
+    // the scene and icon are loaded together with the item
+    for path in referenced_assets(&template) {
+        let file = path.split('#').next().unwrap_or_default();
+        if !root.join(file).is_file() {
+            let position = position_of(&text, &path).unwrap_or(start);
+            diagnostics.push(Diagnostic::new(
+                position,
+                format!("missing asset `{}`", path),
+            ));
+        }
+    }
+
+    if let Err(err) = round_trip(registry, &template) {
+        diagnostics.push(Diagnostic::new(start, err));
+    }
+
+    // follow the parents like the loader does
+    let position = template
+        .extends
+        .as_deref()
+        .and_then(|parent| position_of(&text, parent))
+        .unwrap_or(start);
+
+    let mut visited = vec![file.to_path_buf()];
+    let mut extends = template.extends;
+    while let Some(parent) = extends.take() {
+        let path = root.join(&parent);
+
+        if visited.contains(&path) {
+            diagnostics.push(Diagnostic::new(
+                position,
+                format!("cyclic inheritance through `{}`", parent),
+            ));
+            break;
+        }
+
+        let parsed = std::fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| parse(registry, &text).map_err(|err| err.to_string()));
+
+        match parsed {
+            Ok(template) => extends = template.extends,
+            Err(err) => {
+                diagnostics.push(Diagnostic::new(
+                    position,
+                    format!("invalid parent item `{}`: {}", parent, err),
+                ));
+                break;
+            }
+        }
+
+        visited.push(path);
+    }
+
+    diagnostics
+}
+
+fn referenced_assets(template: &ItemTemplate) -> Vec<String> {
+    template
+        .components
+        .iter()
+        .filter_map(|reflect| {
+            let type_id = reflect.get_represented_type_info()?.type_id();
+            if type_id == TypeId::of::<ItemIcon>() {
+                ItemIcon::from_reflect(&**reflect).map(|ItemIcon(path)| path)
+            } else if type_id == TypeId::of::<ItemScene>() {
+                ItemScene::from_reflect(&**reflect).map(|ItemScene(path)| path)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn parse(registry: &TypeRegistry, text: &str) -> Result<ItemTemplate, ron::error::SpannedError> {
+    let mut deserializer = ron::de::Deserializer::from_str(text)?;
+    ComponentsDeserializer { registry }
+        .deserialize(&mut deserializer)
+        .map_err(|e| deserializer.span_error(e))
+}
+
+/// The canonical form written by the item serializer must read back to the parsed file.
+fn round_trip(registry: &TypeRegistry, template: &ItemTemplate) -> Result<(), String> {
+    let written = template.to_ron(registry).map_err(|err| err.to_string())?;
+    let parsed = parse(registry, &written).map_err(|err| err.to_string())?;
+
+    if !parsed.reflect_eq(template) {
+        let rewritten = parsed.to_ron(registry).map_err(|err| err.to_string())?;
+        return Err(format!(
+            "item does not round-trip:\n{}\nreads back as:\n{}",
+            written, rewritten
+        ));
+    }
+
+    Ok(())
+}
+
+fn position_of(text: &str, needle: &str) -> Option<Position> {
+    let offset = text.find(needle)?;
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let col = before.rsplit('\n').next().map_or(0, |s| s.chars().count()) + 1;
+    Some(Position { line, col })
+}
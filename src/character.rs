@@ -7,15 +7,22 @@ use big_brain::prelude::*;
 mod inventory;
 mod movement;
 
-use self::item::ContainerBundle;
+use self::item::{ContainerBundle, ContainerCapacity, ItemSpawnRequest};
 pub use self::{
     inventory::Inventory,
-    movement::{CachedFinder, FindAndMove},
+    movement::{move_towards, CachedFinder, FindAndMove},
 };
 
 pub const DEFAULT_COLOR: Color = Color::BLACK;
 pub const SLEEP_COLOR: Color = Color::BLUE;
 pub const FARM_COLOR: Color = Color::YELLOW;
+pub const CRAFT_COLOR: Color = Color::ORANGE;
+
+pub const INVENTORY_CAPACITY: ContainerCapacity = ContainerCapacity {
+    slots: 8,
+    weight: 50.0,
+    volume: f32::INFINITY,
+};
 
 pub struct CharacterPlugin;
 
@@ -112,6 +119,7 @@ pub fn spawner_system(
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     items: Res<ItemDatabase>,
+    mut spawn_item: EventWriter<ItemSpawnRequest>,
 ) {
     use rand::rngs::SmallRng;
     use rand::{Rng, SeedableRng};
@@ -123,26 +131,12 @@ pub fn spawner_system(
         model,
     } in events.drain()
     {
-        let mut container_entity = commands.spawn(ContainerBundle::default());
-        let container = container_entity.id();
-
-        if player {
-            container_entity.with_children(|builder| {
-                let sphere = shape::Icosphere {
-                    radius: 0.3,
-                    subdivisions: 3,
-                };
-                builder.spawn((
-                    items.potion.clone(),
-                    PbrBundle {
-                        mesh: meshes.add(sphere.try_into().unwrap()),
-                        material: cache.get_material(&mut materials, Color::RED),
-                        transform: Transform::from_translation(Vec3::new(0.0, 0.0, 0.0)),
-                        ..default()
-                    },
-                ));
-            });
-        }
+        let container = commands
+            .spawn(ContainerBundle {
+                capacity: INVENTORY_CAPACITY,
+                ..default()
+            })
+            .id();
 
         let mut entity = commands.spawn((
             SpatialBundle {
@@ -158,12 +152,24 @@ pub fn spawner_system(
                 current: rng.gen_range(0.0..=100.0),
                 change: 8.0,
             },
+            Hunger {
+                current: rng.gen_range(0.0..=50.0),
+                change: 2.0,
+            },
             Inventory { container },
+            SpeedBuffs::default(),
             create_thinker(),
         ));
 
         if player {
             entity.insert(crate::player::Player);
+
+            // the character asks for its starting items
+            for id in ["potion", "bag"] {
+                if let Some(item) = items.get(id) {
+                    spawn_item.send(ItemSpawnRequest::new(entity.id(), item, container));
+                }
+            }
         }
 
         entity.with_children(|builder| {
@@ -198,6 +204,8 @@ pub fn spawner_system(
 
 fn create_thinker() -> ThinkerBuilder {
     Thinker::highest()
+        .when(RemedyScorer, Use::<item::RestoreFatigue>::default())
+        .when(EatScorer, Use::<item::RestoreHunger>::default())
         .when(
             FatigueScorer::default(),
             Sequence::step((FindAndMove::<House>::new(0.1), Sleep::new(10.0, 30.0))),
@@ -206,9 +214,22 @@ fn create_thinker() -> ThinkerBuilder {
             WorkNeedScorer,
             Sequence::step((FindAndMove::<Field>::new(0.1), Farm::new(30.0))),
         )
+        .when(
+            CraftScorer::new("bread"),
+            Sequence::step((FindAndMove::<House>::new(0.1), Craft::<House>::new("bread"))),
+        )
+        .when(
+            PickUpScorer,
+            Sequence::step((FindGroundItem::new(0.1), PickUp::new(0.5))),
+        )
         .when(
             SellNeedScorer,
             Sequence::step((FindAndMove::<Market>::new(0.1), Sell)),
         )
+        .when(TradeScorer, Trade::default())
+        .when(
+            BuyNeedScorer,
+            Sequence::step((FindAndMove::<Market>::new(0.1), Buy)),
+        )
         .otherwise(Idle)
 }
@@ -35,16 +35,11 @@ impl<T: Component + Clone> FindAndMove<T> {
                     continue;
                 };
 
-                let delta = goal.translation - transform.translation;
-                let distance = delta.length();
-
+                let distance = goal.translation.distance(transform.translation);
                 trace!("Distance to {:?}: {}", std::any::type_name::<T>(), distance);
 
-                if distance > move_to.radius {
-                    let step = (ctrl.speed * time.delta_seconds()).min(distance);
-                    transform.translation += delta.normalize_or_zero() * step;
-                    transform.look_to(delta, Vec3::Y);
-                } else {
+                let step = ctrl.speed * time.delta_seconds();
+                if move_towards(&mut transform, goal.translation, step, move_to.radius) {
                     debug!("Reached {:?}", std::any::type_name::<T>());
                     action.success()
                 }
@@ -61,6 +56,19 @@ impl<T: Component + Clone> FindAndMove<T> {
     }
 }
 
+/// Walks up to `step` towards `goal`, returns whether it is within `radius`.
+pub fn move_towards(transform: &mut Transform, goal: Vec3, step: f32, radius: f32) -> bool {
+    let delta = goal - transform.translation;
+    let distance = delta.length();
+    if distance <= radius {
+        return true;
+    }
+
+    transform.translation += delta.normalize_or_zero() * step.min(distance);
+    transform.look_to(delta, Vec3::Y);
+    false
+}
+
 #[derive(Clone, Default)]
 pub struct CachedFinder {
     target: Option<Entity>,
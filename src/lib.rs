@@ -0,0 +1,8 @@
+pub mod character;
+pub mod game_state;
+pub mod loading;
+pub mod main_menu;
+pub mod mechanics;
+pub mod player;
+pub mod raycast;
+pub mod splash_screen;
@@ -1,9 +1,13 @@
 use crate::{
     character::{CharacterModel, ModelCacheEntry},
     game_state::GameState,
-    mechanics::ItemAsset,
+    mechanics::{
+        craft::RecipeAsset,
+        item::{ItemName, ItemShape},
+        ItemAsset,
+    },
 };
-use bevy::{prelude::*, utils::HashMap};
+use bevy::{asset::AssetPath, prelude::*, utils::HashMap};
 use bevy_asset_loader::prelude::*;
 use bevy_egui::{egui, EguiContexts};
 use iyes_progress::{Progress, ProgressCounter, ProgressPlugin, ProgressSystem};
@@ -14,7 +18,11 @@ impl Plugin for LoadingPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(ProgressPlugin::new(GameState::Loading).continue_to(GameState::MainMenu))
             .add_loading_state(
-                LoadingState::new(GameState::Loading).load_collection::<ItemDatabase>(),
+                LoadingState::new(GameState::Loading)
+                    .load_collection::<ItemCollection>()
+                    .load_collection::<RecipeCollection>()
+                    .init_resource::<ItemDatabase>()
+                    .init_resource::<RecipeDatabase>(),
             )
             .add_systems(
                 Update,
@@ -26,16 +34,146 @@ impl Plugin for LoadingPlugin {
     }
 }
 
+/// Every `*.item.ron` found under `assets/items`.
 #[derive(AssetCollection, Resource)]
+pub struct ItemCollection {
+    #[asset(path = "items", collection(typed))]
+    pub items: Vec<Handle<ItemAsset>>,
+}
+
+/// The path of an asset relative to `folder` without its `extension`, with `/` separators.
+fn asset_id(path: &AssetPath, folder: &str, extension: &str) -> Option<String> {
+    let path = path.path().strip_prefix(folder).ok()?;
+    let path = path.to_str()?.strip_suffix(extension)?;
+    Some(path.replace('\\', "/"))
+}
+
+/// Index over [`ItemCollection`] built once the items are loaded.
+///
+/// An item ID is the path of its file relative to `assets/items` without the
+/// `.item.ron` extension, e.g. `items/food/apple.item.ron` has ID `food/apple`.
+#[derive(Resource)]
 pub struct ItemDatabase {
-    #[asset(path = "items/money.item.ron")]
-    pub money: Handle<ItemAsset>,
+    by_id: HashMap<String, Handle<ItemAsset>>,
+    by_name: HashMap<String, String>,
+}
+
+impl ItemDatabase {
+    pub const FOLDER: &'static str = "items";
+    pub const EXTENSION: &'static str = ".item.ron";
+
+    pub fn item_id(path: &AssetPath) -> Option<String> {
+        asset_id(path, Self::FOLDER, Self::EXTENSION)
+    }
+
+    pub fn insert(&mut self, id: String, name: Option<String>, handle: Handle<ItemAsset>) {
+        if let Some(name) = name {
+            if let Some(other) = self.by_name.insert(name.clone(), id.clone()) {
+                warn!("items `{}` and `{}` share the name `{}`", other, id, name);
+            }
+        }
+        self.by_id.insert(id, handle);
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Handle<ItemAsset>> {
+        self.by_id.get(id)
+    }
+
+    pub fn get_by_name(&self, name: &str) -> Option<&Handle<ItemAsset>> {
+        self.by_name.get(name).and_then(|id| self.get(id))
+    }
+
+    pub fn id_of(&self, handle: &Handle<ItemAsset>) -> Option<&str> {
+        self.by_id
+            .iter()
+            .find_map(|(id, other)| (other == handle).then_some(id.as_str()))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Handle<ItemAsset>)> {
+        self.by_id.iter().map(|(id, handle)| (id.as_str(), handle))
+    }
+}
+
+impl FromWorld for ItemDatabase {
+    fn from_world(world: &mut World) -> Self {
+        let collection = world.resource::<ItemCollection>();
+        let assets = world.resource::<Assets<ItemAsset>>();
+
+        let mut database = Self {
+            by_id: HashMap::default(),
+            by_name: HashMap::default(),
+        };
+
+        for handle in &collection.items {
+            let Some(id) = handle.path().and_then(Self::item_id) else {
+                warn!("skip item without path: {:?}", handle);
+                continue;
+            };
+
+            let name = assets
+                .get(handle)
+                .and_then(ItemAsset::get::<ItemName>)
+                .map(|ItemName(name)| name);
+
+            database.insert(id, name, handle.clone());
+        }
 
-    #[asset(path = "items/raw_food.item.ron")]
-    pub raw_food: Handle<ItemAsset>,
+        info!("loaded {} items", database.by_id.len());
 
-    #[asset(path = "items/potion.item.ron")]
-    pub potion: Handle<ItemAsset>,
+        database
+    }
+}
+
+/// Every `*.recipe.ron` found under `assets/recipes`.
+#[derive(AssetCollection, Resource)]
+pub struct RecipeCollection {
+    #[asset(path = "recipes", collection(typed))]
+    pub recipes: Vec<Handle<RecipeAsset>>,
+}
+
+/// Recipes by ID, made from their path the same way as item IDs.
+#[derive(Resource)]
+pub struct RecipeDatabase {
+    by_id: HashMap<String, Handle<RecipeAsset>>,
+}
+
+impl RecipeDatabase {
+    pub const FOLDER: &'static str = "recipes";
+    pub const EXTENSION: &'static str = ".recipe.ron";
+
+    pub fn recipe_id(path: &AssetPath) -> Option<String> {
+        asset_id(path, Self::FOLDER, Self::EXTENSION)
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Handle<RecipeAsset>> {
+        self.by_id.get(id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Handle<RecipeAsset>)> {
+        self.by_id.iter().map(|(id, handle)| (id.as_str(), handle))
+    }
+}
+
+impl FromWorld for RecipeDatabase {
+    fn from_world(world: &mut World) -> Self {
+        let collection = world.resource::<RecipeCollection>();
+
+        let by_id: HashMap<_, _> = collection
+            .recipes
+            .iter()
+            .filter_map(|handle| {
+                let id = handle.path().and_then(Self::recipe_id);
+                if id.is_none() {
+                    warn!("skip recipe without path: {:?}", handle);
+                }
+                Some((id?, handle.clone()))
+            })
+            .collect();
+
+        info!("loaded {} recipes", by_id.len());
+
+        Self { by_id }
+    }
 }
 
 fn track_fake_long_task<const TOTAL: u32>(time: Res<Time>) -> Progress {
@@ -100,6 +238,7 @@ impl std::hash::Hash for HashedColor {
 #[derive(Resource, Default)]
 pub struct AssetCache {
     model: HashMap<CharacterModel, ModelCacheEntry>,
+    item: HashMap<ItemShape, Handle<Mesh>>,
     material: HashMap<HashedColor, Handle<StandardMaterial>>,
 }
 
@@ -132,6 +271,13 @@ impl AssetCache {
             .clone()
     }
 
+    pub fn get_item_mesh(&mut self, meshes: &mut Assets<Mesh>, shape: ItemShape) -> Handle<Mesh> {
+        self.item
+            .entry(shape)
+            .or_insert_with(|| meshes.add(Mesh::from(shape)))
+            .clone()
+    }
+
     pub fn get_material(
         &mut self,
         materials: &mut Assets<StandardMaterial>,
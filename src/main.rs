@@ -1,20 +1,11 @@
-use crate::character::SpawnCharacter;
-use crate::loading::AssetCache;
 use bevy::{
     diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin},
     pbr::{CascadeShadowConfigBuilder, DirectionalLightShadowMap},
     prelude::*,
     window::PresentMode,
 };
-
-pub mod character;
-pub mod game_state;
-pub mod loading;
-pub mod main_menu;
-pub mod mechanics;
-pub mod player;
-pub mod raycast;
-pub mod splash_screen;
+use lifer::character::SpawnCharacter;
+use lifer::loading::AssetCache;
 
 #[cfg(not(target_env = "msvc"))]
 #[global_allocator]
@@ -23,7 +14,7 @@ static GLOBAL: jemallocator::Jemalloc = jemallocator::Jemalloc;
 fn main() {
     let mut app = App::new();
 
-    app.add_state::<crate::game_state::GameState>();
+    app.add_state::<lifer::game_state::GameState>();
 
     app.add_plugins((
         DefaultPlugins.set(WindowPlugin {
@@ -38,17 +29,17 @@ fn main() {
         LogDiagnosticsPlugin::default(),
         FrameTimeDiagnosticsPlugin,
         bevy_egui::EguiPlugin,
-        crate::splash_screen::SplashScreenPlugin,
-        crate::loading::LoadingPlugin,
-        crate::player::PlayerPlugin,
-        crate::main_menu::MainMenuPlugin,
-        crate::mechanics::MechanicsPlugin,
-        crate::character::CharacterPlugin,
-        crate::raycast::RaycastPlugin,
+        lifer::splash_screen::SplashScreenPlugin,
+        lifer::loading::LoadingPlugin,
+        lifer::player::PlayerPlugin,
+        lifer::main_menu::MainMenuPlugin,
+        lifer::mechanics::MechanicsPlugin,
+        lifer::character::CharacterPlugin,
+        lifer::raycast::RaycastPlugin,
     ));
 
     app.add_systems(Startup, setup_camera);
-    app.add_systems(OnEnter(crate::game_state::GameState::Playing), init_scene);
+    app.add_systems(OnEnter(lifer::game_state::GameState::Playing), init_scene);
 
     app.add_systems(
         PreUpdate,
@@ -94,7 +85,7 @@ fn setup_camera(mut commands: Commands) {
     commands
         .spawn((
             SpatialBundle::from_transform(Transform::from_xyz(-2.5, 0.0, 0.0)),
-            crate::player::CameraController,
+            lifer::player::CameraController,
         ))
         .with_children(|builder| {
             builder.spawn((
@@ -106,7 +97,7 @@ fn setup_camera(mut commands: Commands) {
                     transform: Transform::from_xyz(0.0, 15.0, 20.0).looking_at(Vec3::ZERO, Vec3::Y),
                     ..default()
                 },
-                crate::raycast::PlaneRaycast::Y,
+                lifer::raycast::PlaneRaycast::Y,
             ));
         });
 }
@@ -1,16 +1,34 @@
+pub mod craft;
 pub mod field;
+pub mod ground;
 pub mod house;
 pub mod item;
 pub mod market;
+pub mod trade;
+pub mod treasury;
+pub mod usage;
 
 pub use self::{
+    craft::{Craft, CraftScorer, RecipeAsset},
     field::{Farm, Field, WorkNeedScorer},
+    ground::{DropItem, FindGroundItem, GroundItem, PickUp, PickUpItem, PickUpScorer},
     house::{Fatigue, FatigueScorer, House, Sleep},
     item::{Item, ItemAsset, ItemAssetLoader, ItemAssetLoaderError, ItemSpawnError},
-    market::{Market, Sell, SellNeedScorer},
+    market::{
+        Buy, BuyNeedScorer, Market, MarketGoods, MarketStock, OrderBook, PriceHistory, Sell,
+        SellNeedScorer,
+    },
+    trade::{Trade, TradeScorer},
+    treasury::{TaxConfig, Treasury, TreasuryLedger},
+    usage::{EatScorer, Hunger, RemedyScorer, SpeedBuffs, Use, UseItem},
 };
 
-use crate::{game_state::GameState, loading::AssetCache};
+use self::item::{Consumable, ContainerBundle, ItemSpawnRequest};
+use crate::{
+    game_state::GameState,
+    loading::{AssetCache, ItemDatabase},
+    player::Selectable,
+};
 use bevy::prelude::*;
 
 pub const FIELD_COLOR: Color = Color::YELLOW;
@@ -22,10 +40,15 @@ pub struct MechanicsPlugin;
 impl Plugin for MechanicsPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins((
+            self::craft::CraftPlugin,
             self::field::FieldPlugin,
+            self::ground::GroundPlugin,
             self::house::HousePlugin,
             self::item::ItemPlugin,
             self::market::MarketPlugin,
+            self::trade::TradePlugin,
+            self::treasury::TreasuryPlugin,
+            self::usage::UsagePlugin,
         ))
         .add_systems(OnEnter(GameState::Playing), spawn_scene)
         .add_systems(OnExit(GameState::Playing), despawn_scene);
@@ -37,6 +60,8 @@ pub fn spawn_scene(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    items: Res<ItemDatabase>,
+    mut spawn_item: EventWriter<ItemSpawnRequest>,
 ) {
     let rotation = Transform::from_rotation(Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2));
     let model = meshes.add(shape::Circle::new(0.5).into());
@@ -57,13 +82,45 @@ pub fn spawn_scene(
         ..default()
     });
 
-    // marketplace
-    commands.spawn(Market).insert(PbrBundle {
-        mesh: model,
-        material: cache.get_material(&mut materials, MARKET_COLOR),
-        transform: rotation.with_translation(Vec3::new(0.0, 0.0, 5.0)),
-        ..default()
-    });
+    // marketplace, restocked from the first frame on
+    let container = commands
+        .spawn(ContainerBundle {
+            capacity: market::MARKET_CAPACITY,
+            ..default()
+        })
+        .id();
+    let escrow = commands.spawn(ContainerBundle::default()).id();
+    let market = commands
+        .spawn((
+            Market,
+            MarketGoods::default(),
+            MarketStock { container },
+            OrderBook::new(escrow),
+            Selectable,
+        ))
+        .insert(PbrBundle {
+            mesh: model,
+            material: cache.get_material(&mut materials, MARKET_COLOR),
+            transform: rotation.with_translation(Vec3::new(0.0, 0.0, 5.0)),
+            ..default()
+        })
+        .push_children(&[container, escrow])
+        .id();
+
+    // the till the market pays sellers from
+    if let Some(money) = items.get("money") {
+        let funds = Consumable {
+            current: market::MARKET_FUNDS,
+            maximum: f32::INFINITY,
+        };
+        spawn_item.send(ItemSpawnRequest::new(market, money, container).with_override(funds));
+    }
+
+    // town treasury, filled by taxes
+    let container = commands.spawn(ContainerBundle::default()).id();
+    commands
+        .spawn((Treasury { container }, SpatialBundle::default()))
+        .push_children(&[container]);
 }
 
 pub fn despawn_scene(
@@ -71,6 +128,8 @@ pub fn despawn_scene(
     fields: Query<Entity, With<Field>>,
     houses: Query<Entity, With<House>>,
     markets: Query<Entity, With<Market>>,
+    ground: Query<Entity, With<GroundItem>>,
+    treasuries: Query<Entity, With<Treasury>>,
 ) {
     for entity in &fields {
         commands.entity(entity).despawn_recursive();
@@ -81,4 +140,10 @@ pub fn despawn_scene(
     for entity in &markets {
         commands.entity(entity).despawn_recursive();
     }
+    for entity in &ground {
+        commands.entity(entity).despawn_recursive();
+    }
+    for entity in &treasuries {
+        commands.entity(entity).despawn_recursive();
+    }
 }
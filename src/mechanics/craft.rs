@@ -0,0 +1,341 @@
+use super::item::{
+    ContainerTransaction, ItemAsset, ItemFilter, ReadCapacity, ReadConsumable, TransactionResult,
+};
+use super::House;
+use crate::{
+    character::{CharacterController, Inventory, CRAFT_COLOR, DEFAULT_COLOR},
+    game_state::GameState,
+    loading::{ItemDatabase, RecipeDatabase},
+};
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use big_brain::prelude::*;
+
+mod recipe;
+
+pub use self::recipe::{RecipeAsset, RecipeAssetLoader, RecipeAssetLoaderError, RecipeItem};
+
+/// How close to the workstation a character has to be to use it.
+pub const WORKSTATION_RANGE: f32 = 1.0;
+
+pub struct CraftPlugin;
+
+impl Plugin for CraftPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<RecipeAsset>()
+            .init_asset_loader::<RecipeAssetLoader>()
+            .add_systems(
+                PreUpdate,
+                Craft::<House>::system
+                    .in_set(BigBrainSet::Actions)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                PreUpdate,
+                craft_scorer
+                    .in_set(BigBrainSet::Scorers)
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+/// A recipe with its items resolved to handles.
+pub struct ResolvedRecipe<'a> {
+    pub inputs: Vec<(Handle<ItemAsset>, f32)>,
+    pub outputs: Vec<(Handle<ItemAsset>, f32)>,
+    pub duration: f32,
+    pub workstation: Option<&'a str>,
+}
+
+#[derive(SystemParam)]
+pub struct Recipes<'w> {
+    recipes: Res<'w, RecipeDatabase>,
+    assets: Res<'w, Assets<RecipeAsset>>,
+    items: Res<'w, ItemDatabase>,
+}
+
+impl Recipes<'_> {
+    /// `None` when the recipe or one of its items does not exist.
+    pub fn get(&self, id: &str) -> Option<ResolvedRecipe<'_>> {
+        let recipe = self.assets.get(self.recipes.get(id)?)?;
+
+        let resolve = |items: &[RecipeItem]| {
+            items
+                .iter()
+                .map(|input| Some((self.items.get(&input.item)?.clone(), input.quantity)))
+                .collect::<Option<Vec<_>>>()
+        };
+
+        Some(ResolvedRecipe {
+            inputs: resolve(&recipe.inputs)?,
+            outputs: resolve(&recipe.outputs)?,
+            duration: recipe.duration,
+            workstation: recipe.workstation.as_deref(),
+        })
+    }
+}
+
+/// Crafting 🔨 at the workstation marked by `T`
+#[derive(Component, Clone, ActionSpawn)]
+pub struct Craft<T: Component + Clone> {
+    pub recipe: String,
+    /// Time spent so far, the inputs are only taken once the work is done.
+    elapsed: Option<f32>,
+    marker: std::marker::PhantomData<T>,
+}
+
+impl<T: Component + Clone + TypePath> Craft<T> {
+    pub fn new(recipe: impl Into<String>) -> Self {
+        Self {
+            recipe: recipe.into(),
+            elapsed: None,
+            marker: std::marker::PhantomData,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn system(
+        time: Res<Time<Virtual>>,
+        mut actors: Query<(&Inventory, &Transform, &mut CharacterController)>,
+        mut query: Query<(Entity, ActionQuery, &mut Self)>,
+        stations: Query<&Transform, With<T>>,
+        mut pending: Local<HashSet<Entity>>,
+
+        recipes: Recipes,
+        consumable: ReadConsumable,
+        mut transactions: EventWriter<ContainerTransaction>,
+        mut results: EventReader<TransactionResult>,
+    ) {
+        let mut results: HashMap<Entity, _> = results
+            .read()
+            .map(|event| (event.requester, event.result.clone()))
+            .collect();
+
+        for (entity, mut action, mut craft) in &mut query {
+            let (inventory, transform, mut ctrl) = actors.get_mut(action.actor()).unwrap();
+
+            let Some(recipe) = recipes.get(&craft.recipe) else {
+                warn!("unknown recipe `{}`", craft.recipe);
+                action.failure();
+                continue;
+            };
+
+            if action.is_executing() {
+                if pending.contains(&entity) {
+                    // waiting for the inputs to be swapped for the outputs
+                    if let Some(result) = results.remove(&entity) {
+                        pending.remove(&entity);
+                        ctrl.color = DEFAULT_COLOR;
+                        match result {
+                            Ok(()) => {
+                                debug!("Crafted `{}`!", craft.recipe);
+                                action.success();
+                            }
+                            Err(err) => {
+                                debug!("Crafting `{}` failed: {}", craft.recipe, err);
+                                action.failure();
+                            }
+                        }
+                    }
+                } else if let Some(elapsed) = &mut craft.elapsed {
+                    trace!("Crafting...");
+                    ctrl.color = CRAFT_COLOR;
+                    *elapsed += time.delta_seconds();
+
+                    if *elapsed >= recipe.duration {
+                        craft.elapsed = None;
+
+                        // inputs and outputs in one transaction, either both happen or neither
+                        let transaction = ContainerTransaction::new(entity);
+                        let Some(mut transaction) =
+                            take_inputs(&consumable, &recipe, inventory.container, transaction)
+                        else {
+                            debug!("Inputs for `{}` are gone!", craft.recipe);
+                            ctrl.color = DEFAULT_COLOR;
+                            action.failure();
+                            continue;
+                        };
+                        for (item, quantity) in &recipe.outputs {
+                            transaction =
+                                transaction.with_create(item, *quantity, inventory.container);
+                        }
+                        transactions.send(transaction);
+                        pending.insert(entity);
+                    }
+                } else {
+                    let at_workstation = match recipe.workstation {
+                        None => true,
+                        Some(workstation) if workstation == T::type_path() => {
+                            stations.iter().any(|station| {
+                                station.translation.distance(transform.translation)
+                                    <= WORKSTATION_RANGE
+                            })
+                        }
+                        Some(_) => false,
+                    };
+
+                    if !at_workstation {
+                        debug!("Not at the workstation for `{}`!", craft.recipe);
+                        action.failure();
+                    } else if !has_inputs(&consumable, &recipe, inventory.container) {
+                        debug!("Missing inputs for `{}`!", craft.recipe);
+                        action.failure();
+                    } else {
+                        craft.elapsed = Some(0.0);
+                    }
+                }
+            }
+
+            if action.is_cancelled() {
+                debug!("Crafting was interrupted.");
+
+                // nothing was taken yet, a transaction already sent still completes the work
+                craft.elapsed = None;
+                pending.remove(&entity);
+                ctrl.color = DEFAULT_COLOR;
+                action.failure();
+            }
+        }
+    }
+}
+
+fn has_inputs(consumable: &ReadConsumable, recipe: &ResolvedRecipe, container: Entity) -> bool {
+    recipe.inputs.iter().all(|(item, quantity)| {
+        consumable.sum_recursive(ItemFilter::from(item), container) >= *quantity
+    })
+}
+
+/// Destroys the inputs from the `container` and the containers inside it.
+///
+/// `None` when some are missing, e.g. taken away while crafting, a partial destroy
+/// would craft the outputs for less.
+fn take_inputs(
+    consumable: &ReadConsumable,
+    recipe: &ResolvedRecipe,
+    container: Entity,
+    mut transaction: ContainerTransaction,
+) -> Option<ContainerTransaction> {
+    for (item, quantity) in &recipe.inputs {
+        let mut left = *quantity;
+        for container in consumable.containers(container) {
+            let taken = left.min(consumable.total(item, container));
+            if taken > 0.0 {
+                transaction = transaction.with_destroy(item, taken, container);
+                left -= taken;
+            }
+        }
+        if left > 0.0 {
+            return None;
+        }
+    }
+    Some(transaction)
+}
+
+/// Scores crafting the `recipe` once the inventory holds its inputs and has room for the outputs.
+#[derive(Component, Clone, ScorerSpawn)]
+pub struct CraftScorer {
+    pub recipe: String,
+}
+
+impl CraftScorer {
+    pub fn new(recipe: impl Into<String>) -> Self {
+        Self {
+            recipe: recipe.into(),
+        }
+    }
+}
+
+pub fn craft_scorer(
+    actors: Query<&Inventory>,
+    mut query: Query<(ScorerQuery, &CraftScorer)>,
+
+    recipes: Recipes,
+    consumable: ReadConsumable,
+    capacity: ReadCapacity,
+) {
+    for (mut score, scorer) in &mut query {
+        let inventory = actors.get(score.actor()).expect("actor");
+        let Some(recipe) = recipes.get(&scorer.recipe) else {
+            score.set(0.0);
+            continue;
+        };
+
+        let room = recipe.outputs.iter().all(|(item, quantity)| {
+            let new_stack = consumable.get(item, inventory.container, |_| ()).is_none();
+            capacity.room_for(inventory.container, item, new_stack) >= *quantity
+        });
+        // below work, a harvest is only turned into something else once it is done
+        let craft = room && has_inputs(&consumable, &recipe, inventory.container);
+        score.set(if craft { 0.5 } else { 0.0 });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mechanics::item::{
+        update_container_index, Consumable, ContainerIndex, ItemAdded, ItemQuantityChanged,
+        ItemRemoved,
+    };
+    use bevy::ecs::system::RunSystemOnce;
+
+    const FLOUR: Handle<ItemAsset> = Handle::weak_from_u128(1);
+    const BREAD: Handle<ItemAsset> = Handle::weak_from_u128(2);
+
+    fn recipe() -> ResolvedRecipe<'static> {
+        ResolvedRecipe {
+            inputs: vec![(FLOUR, 2.0)],
+            outputs: vec![(BREAD, 1.0)],
+            duration: 1.0,
+            workstation: None,
+        }
+    }
+
+    fn ops(app: &mut App, inventory: Entity) -> Option<usize> {
+        app.world
+            .run_system_once(move |consumable: ReadConsumable| {
+                let transaction = ContainerTransaction::new(Entity::PLACEHOLDER);
+                take_inputs(&consumable, &recipe(), inventory, transaction)
+                    .map(|transaction| transaction.ops.len())
+            })
+    }
+
+    #[test]
+    fn inputs_taken_away_while_crafting_are_not_destroyed_in_part() {
+        let mut app = App::new();
+        app.init_resource::<Assets<ItemAsset>>()
+            .add_event::<ItemAdded>()
+            .add_event::<ItemRemoved>()
+            .add_event::<ItemQuantityChanged>()
+            .add_systems(Update, update_container_index);
+
+        let inventory = app.world.spawn(ContainerIndex::default()).id();
+        let stack = Consumable {
+            current: 3.0,
+            maximum: 10.0,
+        };
+        let item = app.world.spawn((FLOUR, stack)).set_parent(inventory).id();
+        app.world.send_event(ItemAdded {
+            container: inventory,
+            item,
+            asset: FLOUR,
+        });
+        app.update();
+        assert_eq!(ops(&mut app, inventory), Some(1));
+
+        // eaten before the work is done
+        app.world.get_mut::<Consumable>(item).unwrap().current = 1.0;
+        app.world.send_event(ItemQuantityChanged {
+            container: inventory,
+            item,
+            asset: FLOUR,
+            previous: 3.0,
+            current: 1.0,
+        });
+        app.update();
+        assert_eq!(ops(&mut app, inventory), None);
+    }
+}
@@ -0,0 +1,65 @@
+use bevy::{
+    asset::{io::Reader, Asset, AssetLoader, AsyncReadExt, LoadContext},
+    reflect::TypePath,
+    utils::{
+        thiserror::{self, Error},
+        BoxedFuture,
+    },
+};
+use serde::Deserialize;
+
+/// Turns input items into output items, read from `*.recipe.ron`.
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub struct RecipeAsset {
+    pub inputs: Vec<RecipeItem>,
+    pub outputs: Vec<RecipeItem>,
+    /// Seconds of work.
+    pub duration: f32,
+    /// Type path of the component marking where the recipe is made, anywhere when `None`.
+    #[serde(default)]
+    pub workstation: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RecipeItem {
+    /// Item ID as listed by [`ItemDatabase`](crate::loading::ItemDatabase).
+    pub item: String,
+    pub quantity: f32,
+}
+
+#[derive(Default)]
+pub struct RecipeAssetLoader;
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum RecipeAssetLoaderError {
+    /// An [IO](std::io) Error
+    #[error("Could not load asset: {0}")]
+    Io(#[from] std::io::Error),
+    /// A [RON](ron) Error
+    #[error("Could not parse RON: {0}")]
+    RonSpannedError(#[from] ron::error::SpannedError),
+}
+
+impl AssetLoader for RecipeAssetLoader {
+    type Asset = RecipeAsset;
+    type Settings = ();
+    type Error = RecipeAssetLoaderError;
+
+    fn extensions(&self) -> &[&str] {
+        &["recipe.ron"]
+    }
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _: &'a Self::Settings,
+        _: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            Ok(ron::de::from_bytes(&bytes)?)
+        })
+    }
+}
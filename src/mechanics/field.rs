@@ -1,13 +1,17 @@
-use super::item::{Consumable, ReadConsumable, WriteConsumable};
+use super::item::{
+    Consumable, ContainerRejected, ItemAsset, Perishable, PutInContainer, ReadCapacity,
+    ReadConsumable, WriteContainer,
+};
+use super::GroundItem;
 use crate::{
     character::{CharacterController, FindAndMove, Inventory, DEFAULT_COLOR, FARM_COLOR},
     game_state::GameState,
     loading::ItemDatabase,
 };
-use bevy::prelude::*;
+use bevy::{ecs::system::lifetimeless::Write, prelude::*, utils::HashMap};
 use big_brain::prelude::*;
 
-#[derive(Component, Clone)]
+#[derive(Component, Clone, TypePath)]
 pub struct Field;
 
 pub struct FieldPlugin;
@@ -37,28 +41,71 @@ impl Farm {
     }
 }
 
+/// Items outside of any container which are not lying on the ground either.
+pub type LooseItem = (
+    With<Handle<ItemAsset>>,
+    Without<Parent>,
+    Without<GroundItem>,
+);
+
+pub type WriteHarvest<'w, 's> =
+    WriteContainer<'w, 's, (Write<Consumable>, Option<Write<Perishable>>)>;
+
+#[allow(clippy::too_many_arguments)]
 pub fn farm_action(
     time: Res<Time<Virtual>>,
     mut actors: Query<(&Inventory, &mut CharacterController)>,
     mut query: Query<(ActionQuery, &Farm)>,
 
     items: Res<ItemDatabase>,
-    children: Query<&Children>,
-    mut consumable: WriteConsumable,
+    mut containers: ParamSet<(ReadCapacity, WriteHarvest)>,
+    mut rejected: EventReader<ContainerRejected>,
+    loose: Query<(), LooseItem>,
+    mut put: EventWriter<PutInContainer>,
     mut commands: Commands,
 ) {
+    let Some(raw_food) = items.get("raw_food") else {
+        return;
+    };
+
+    let mut rejected_items: HashMap<Entity, Vec<Entity>> = HashMap::new();
+    for event in rejected.read() {
+        rejected_items
+            .entry(event.container)
+            .or_default()
+            .push(event.item);
+    }
+
     for (mut action, farm) in &mut query {
         let (inventory, mut ctrl) = actors.get_mut(action.actor()).unwrap();
-        let container = children.get(inventory.container).ok();
+
+        // stacks planted here which did not fit are neither in a container nor on the ground
+        let rejected = rejected_items.get(&inventory.container);
+        for &item in rejected.into_iter().flatten() {
+            if loose.contains(item) {
+                commands.entity(item).despawn_recursive();
+            }
+        }
 
         if action.is_executing() {
             trace!("Farming...");
-            let add = farm.per_second * time.delta_seconds();
+            let capacity = containers.p0();
+            let room = capacity.room_for(inventory.container, raw_food, false);
+            let slot = capacity.room_for(inventory.container, raw_food, true);
+            let add = (farm.per_second * time.delta_seconds()).min(room);
 
-            let is_maximum = consumable.get(container, &items.raw_food, |mut cons| {
-                cons.current = (cons.current + add).clamp(0.0, cons.maximum);
-                cons.current == cons.maximum
-            });
+            let is_maximum =
+                containers
+                    .p1()
+                    .get(inventory.container, raw_food, |(mut cons, perishable)| {
+                        let before = cons.current;
+                        cons.current = (cons.current + add).clamp(0.0, cons.maximum);
+                        // the harvest is fresh
+                        if let Some(mut perishable) = perishable {
+                            perishable.blend(before, cons.current - before, 1.0);
+                        }
+                        cons.current == cons.maximum
+                    });
 
             if let Some(is_maximum) = is_maximum {
                 ctrl.color = FARM_COLOR;
@@ -66,11 +113,22 @@ pub fn farm_action(
                     debug!("Inventory full!");
                     ctrl.color = DEFAULT_COLOR;
                     action.success();
+                } else if room <= 0.0 {
+                    debug!("Backpack full!");
+                    ctrl.color = DEFAULT_COLOR;
+                    action.failure();
                 }
+            } else if slot <= 0.0 || rejected.is_some() {
+                debug!("No room for food in backpack!");
+                ctrl.color = DEFAULT_COLOR;
+                action.failure();
             } else {
                 // add empty raw_food and try next frame
-                let food = commands.spawn(items.raw_food.clone()).id();
-                commands.entity(inventory.container).add_child(food);
+                let item = commands.spawn(raw_food.clone()).id();
+                put.send(PutInContainer {
+                    item,
+                    to: inventory.container,
+                });
             }
         }
 
@@ -89,14 +147,20 @@ pub fn work_need_scorer(
     actors: Query<&Inventory>,
     mut query: Query<ScorerQuery, With<WorkNeedScorer>>,
 
-    children: Query<&Children>,
     items: Res<ItemDatabase>,
     consumable: ReadConsumable,
+    capacity: ReadCapacity,
 ) {
+    let Some(raw_food) = items.get("raw_food") else {
+        return;
+    };
+
     for mut score in &mut query {
         let inventory = actors.get(score.actor()).expect("actor");
-        let children = children.get(inventory.container).ok();
-        let full = consumable.get_or(&items.raw_food, children, false, Consumable::is_full);
-        score.set(if !full { 0.6 } else { 0.0 });
+        // the stack farming fills up, other food does not keep anyone from work
+        let full = consumable.get(raw_food, inventory.container, |food| food.is_full());
+        let room = capacity.room_for(inventory.container, raw_food, full.is_none());
+        let work = full != Some(true) && room > 0.0;
+        score.set(if work { 0.6 } else { 0.0 });
     }
 }
@@ -0,0 +1,302 @@
+use super::item::{Admission, ContainerRejected, ItemAsset, ReadCapacity, TakeOutOfContainer};
+use crate::{
+    character::{move_towards, CharacterController, Inventory},
+    game_state::GameState,
+};
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use big_brain::prelude::*;
+
+/// How far characters look for loose items worth picking up.
+pub const PICK_UP_RANGE: f32 = 5.0;
+
+/// An item lying in the world outside of any container.
+#[derive(Component, Clone, Debug, Default)]
+pub struct GroundItem {
+    /// Container the item was dropped from, its owner leaves it lying.
+    pub dropped_from: Option<Entity>,
+}
+
+pub struct GroundPlugin;
+
+impl Plugin for GroundPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DropItem>()
+            .add_event::<PickUpItem>()
+            .add_systems(
+                PreUpdate,
+                (
+                    (pick_up_action, find_ground_item_action).in_set(BigBrainSet::Actions),
+                    pick_up_scorer.in_set(BigBrainSet::Scorers),
+                )
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                PostUpdate,
+                (take_out_to_ground, drop_items, pick_up_items).chain(),
+            );
+    }
+}
+
+/// Takes the item out of `from` and places it on the ground at `position`.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct DropItem {
+    pub item: Entity,
+    pub from: Entity,
+    pub position: Vec3,
+}
+
+/// Puts a ground item in a container, what does not fit stays on the ground.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct PickUpItem {
+    pub item: Entity,
+    pub to: Entity,
+}
+
+/// Items taken out of a container are dropped where the container is.
+pub fn take_out_to_ground(
+    mut events: ResMut<Events<TakeOutOfContainer>>,
+    containers: Query<&GlobalTransform>,
+    mut drop: EventWriter<DropItem>,
+) {
+    for TakeOutOfContainer { item, from } in events.drain() {
+        let position = containers
+            .get(from)
+            .map_or(Vec3::ZERO, |transform| transform.translation());
+        drop.send(DropItem {
+            item,
+            from,
+            position,
+        });
+    }
+}
+
+/// Items carry their visuals, out of a container they show up where they are placed.
+pub fn drop_items(
+    mut commands: Commands,
+    mut events: ResMut<Events<DropItem>>,
+    items: Query<(), With<Handle<ItemAsset>>>,
+) {
+    for DropItem {
+        item,
+        from,
+        position,
+    } in events.drain()
+    {
+        if !items.contains(item) {
+            warn!("drop of {:?} which is not an item", item);
+            continue;
+        }
+
+        commands.entity(from).remove_children(&[item]);
+        let ground = GroundItem {
+            dropped_from: Some(from),
+        };
+        let transform = Transform::from_translation(position);
+        commands.entity(item).insert((ground, transform));
+    }
+}
+
+pub fn pick_up_items(
+    mut commands: Commands,
+    mut events: ResMut<Events<PickUpItem>>,
+    mut taken: Local<HashSet<Entity>>,
+    ground: Query<(&GroundItem, &Transform)>,
+    mut admission: Admission,
+) {
+    taken.clear();
+    admission.clear();
+
+    for PickUpItem { item, to } in events.drain() {
+        let Ok((ground_item, transform)) = ground.get(item) else {
+            continue;
+        };
+
+        // someone else was faster
+        if !taken.insert(item) {
+            continue;
+        }
+
+        let admitted = admission.admit(item, to, |entity| {
+            entity.insert((
+                ground_item.clone(),
+                SpatialBundle::from_transform(*transform),
+            ));
+        });
+        if admitted {
+            commands
+                .entity(item)
+                .remove::<GroundItem>()
+                .insert((Transform::IDENTITY, Visibility::Inherited));
+            commands.entity(to).add_child(item);
+        }
+    }
+}
+
+/// Picking up 🫳 the closest item in reach, after walking over to it.
+#[derive(Component, Clone, ActionSpawn)]
+pub struct PickUp {
+    pub radius: f32,
+}
+
+impl PickUp {
+    pub fn new(radius: f32) -> Self {
+        Self { radius }
+    }
+}
+
+pub fn pick_up_action(
+    actors: Query<(&Inventory, &Transform)>,
+    mut query: Query<(Entity, ActionQuery, &PickUp)>,
+    ground: Query<(Entity, &Transform, &GroundItem)>,
+    parents: Query<&Parent>,
+    mut pending: Local<HashMap<Entity, Entity>>,
+    mut events: EventWriter<PickUpItem>,
+    mut rejected: EventReader<ContainerRejected>,
+) {
+    let rejected: HashSet<_> = rejected
+        .read()
+        .map(|rejected| (rejected.item, rejected.container))
+        .collect();
+
+    for (entity, mut action, pick_up) in &mut query {
+        if action.is_executing() {
+            let (inventory, transform) = actors.get(action.actor()).unwrap();
+
+            if let Some(&item) = pending.get(&entity) {
+                // waiting for the inventory to take it
+                if rejected.contains(&(item, inventory.container)) {
+                    debug!("No room to pick it up!");
+                    pending.remove(&entity);
+                    action.failure();
+                } else if parents
+                    .get(item)
+                    .is_ok_and(|p| p.get() == inventory.container)
+                {
+                    debug!("Picked up!");
+                    pending.remove(&entity);
+                    action.success();
+                } else if !ground.contains(item) {
+                    debug!("Someone else picked it up.");
+                    pending.remove(&entity);
+                    action.failure();
+                }
+                continue;
+            }
+
+            let item = ground
+                .iter()
+                .filter(|(_, _, ground)| ground.dropped_from != Some(inventory.container))
+                .map(|(item, at, _)| (item, at.translation.distance(transform.translation)))
+                .filter(|(_, distance)| *distance <= pick_up.radius)
+                .min_by(|(_, a), (_, b)| f32::total_cmp(a, b));
+
+            if let Some((item, _)) = item {
+                events.send(PickUpItem {
+                    item,
+                    to: inventory.container,
+                });
+                pending.insert(entity, item);
+            } else {
+                debug!("Nothing to pick up here.");
+                action.failure();
+            }
+        }
+
+        if action.is_cancelled() {
+            pending.remove(&entity);
+            action.failure();
+        }
+    }
+}
+
+/// Walking 🚶 to the closest item worth picking up, leaving what the character dropped itself.
+#[derive(Component, Clone, ActionSpawn)]
+pub struct FindGroundItem {
+    radius: f32,
+    target: Option<Entity>,
+}
+
+impl FindGroundItem {
+    pub fn new(radius: f32) -> Self {
+        Self {
+            radius,
+            target: None,
+        }
+    }
+}
+
+pub fn find_ground_item_action(
+    time: Res<Time<Virtual>>,
+    mut actors: Query<(&Inventory, &mut Transform, &CharacterController), Without<GroundItem>>,
+    mut query: Query<(ActionQuery, &mut FindGroundItem)>,
+    ground: Query<(Entity, &Transform, &Handle<ItemAsset>, &GroundItem)>,
+    capacity: ReadCapacity,
+) {
+    for (mut action, mut find) in &mut query {
+        if action.is_executing() {
+            let (inventory, mut transform, ctrl) = actors.get_mut(action.actor()).unwrap();
+
+            // the same items the scorer is looking at
+            let target = match find.target {
+                Some(target) => ground.get(target).ok(),
+                None => ground
+                    .iter()
+                    .filter(|(_, at, item, ground)| {
+                        ground.dropped_from != Some(inventory.container)
+                            && at.translation.distance(transform.translation) <= PICK_UP_RANGE
+                            && capacity.room_for(inventory.container, item, true) > 0.0
+                    })
+                    .min_by(|(_, a, _, _), (_, b, _, _)| {
+                        let a = a.translation.distance_squared(transform.translation);
+                        let b = b.translation.distance_squared(transform.translation);
+                        f32::total_cmp(&a, &b)
+                    }),
+            };
+            let Some((target, at, _, _)) = target else {
+                debug!("No ground item to walk to.");
+                find.target = None;
+                action.failure();
+                continue;
+            };
+            find.target = Some(target);
+
+            let step = ctrl.speed * time.delta_seconds();
+            if move_towards(&mut transform, at.translation, step, find.radius) {
+                debug!("Reached the ground item");
+                find.target = None;
+                action.success();
+            }
+        }
+
+        if action.is_cancelled() {
+            find.target = None;
+            action.failure();
+        }
+    }
+}
+
+#[derive(Component, Clone, Default, ScorerSpawn)]
+pub struct PickUpScorer;
+
+pub fn pick_up_scorer(
+    actors: Query<(&Inventory, &Transform)>,
+    mut query: Query<ScorerQuery, With<PickUpScorer>>,
+    ground: Query<(&Transform, &Handle<ItemAsset>, &GroundItem)>,
+    capacity: ReadCapacity,
+) {
+    for mut score in &mut query {
+        let (inventory, transform) = actors.get(score.actor()).expect("actor");
+
+        // a quick detour for anything close by which fits in the backpack,
+        // but not for what the character just dropped itself
+        let worth = ground.iter().any(|(item_transform, item, ground)| {
+            ground.dropped_from != Some(inventory.container)
+                && item_transform.translation.distance(transform.translation) <= PICK_UP_RANGE
+                && capacity.room_for(inventory.container, item, true) > 0.0
+        });
+        score.set(if worth { 0.7 } else { 0.0 });
+    }
+}
@@ -5,7 +5,7 @@ use crate::{
 use bevy::prelude::*;
 use big_brain::prelude::*;
 
-#[derive(Component, Clone)]
+#[derive(Component, Clone, TypePath)]
 pub struct House;
 
 pub struct HousePlugin;
@@ -1,15 +1,46 @@
+use crate::game_state::GameState;
 use bevy::prelude::*;
 
 mod asset;
+mod capacity;
+mod change;
 mod consumable;
 mod containter;
+mod decay;
+mod effect;
+mod index;
+mod model;
+mod reload;
 mod spawn;
+mod stack;
+mod transaction;
 
 pub use self::{
-    asset::{ItemAsset, ItemAssetLoader, ItemAssetLoaderError},
+    asset::{
+        ComponentsDeserializer, ComponentsSerializer, ItemAsset, ItemAssetLoader,
+        ItemAssetLoaderError, ItemAssetSaver, ItemAssetSaverError, ItemTemplate,
+    },
+    capacity::{
+        ContainerCapacity, ContainerLoad, ItemSize, ItemVolume, ItemWeight, Loads, Overfill,
+        ReadCapacity,
+    },
+    change::{ItemAdded, ItemQuantityChanged, ItemRemoved},
     consumable::{Consumable, ReadConsumable, WriteConsumable},
-    containter::{Container, ContainerBundle, ReadContainer, WriteContainer},
-    spawn::ItemSpawnError,
+    containter::{
+        Admission, Container, ContainerBundle, ContainerRejected, ItemFilter, ItemMatcher,
+        MoveBetweenContainers, PutInContainer, ReadContainer, TakeOutOfContainer, WriteContainer,
+    },
+    decay::{Durability, Perishable},
+    effect::{RestoreFatigue, RestoreHunger, SpeedBuff},
+    index::{update_container_index, ContainerIndex},
+    model::{ItemIcon, ItemModel, ItemModels, ItemScene, ItemShape},
+    reload::{ItemHotReload, ReflectReloadItem, ReloadItem},
+    spawn::{
+        item_component, item_registration, ItemComponents, ItemSpawnError, ItemSpawnRequest,
+        ItemSpawnResult,
+    },
+    stack::{MergeStacks, SplitStack, StackResult, TransferStack},
+    transaction::{ContainerTransaction, TransactionError, TransactionOp, TransactionResult},
 };
 
 #[derive(Component)]
@@ -19,26 +50,95 @@ pub struct Item;
 #[reflect(Component)]
 pub struct ItemName(pub String);
 
+/// Free-form labels like `"food"`, shared by items which are used the same way.
+#[derive(Component, Clone, Debug, Default, Reflect)]
+#[reflect(Component)]
+pub struct ItemTags(pub Vec<String>);
+
+impl ItemTags {
+    pub fn contains(&self, tag: &str) -> bool {
+        self.0.iter().any(|t| t == tag)
+    }
+}
+
+/// Money a single unit of the item is worth at the market.
+#[derive(Component, Clone, Copy, Debug, Default, Reflect)]
+#[reflect(Component)]
+pub struct BaseValue(pub f32);
+
 pub struct ItemPlugin;
 
 impl Plugin for ItemPlugin {
     fn build(&self, app: &mut App) {
         app.init_asset::<ItemAsset>()
             .init_asset_loader::<ItemAssetLoader>()
-            .add_event::<self::containter::PutInContainer>()
-            .add_event::<self::containter::MoveBetweenContainers>()
-            .add_event::<self::containter::TakeOutOfContainer>()
+            .add_event::<PutInContainer>()
+            .add_event::<MoveBetweenContainers>()
+            .add_event::<TakeOutOfContainer>()
+            .add_event::<ContainerRejected>()
+            .add_event::<SplitStack>()
+            .add_event::<MergeStacks>()
+            .add_event::<TransferStack>()
+            .add_event::<StackResult>()
+            .add_event::<ContainerTransaction>()
+            .add_event::<TransactionResult>()
+            .add_event::<ItemAdded>()
+            .add_event::<ItemRemoved>()
+            .add_event::<ItemQuantityChanged>()
+            .add_event::<ItemSpawnRequest>()
+            .add_event::<ItemSpawnResult>()
             .register_type::<Consumable>()
+            .register_type::<ContainerCapacity>()
+            .register_type::<ItemWeight>()
+            .register_type::<ItemVolume>()
             .register_type::<ItemName>()
-            .add_systems(SpawnScene, self::spawn::spawn_items_system)
+            .register_type::<ItemTags>()
+            .register_type::<BaseValue>()
+            .register_type::<ItemModel>()
+            .register_type::<ItemScene>()
+            .register_type::<ItemIcon>()
+            .register_type::<Perishable>()
+            .register_type::<Durability>()
+            .register_type::<RestoreFatigue>()
+            .register_type::<RestoreHunger>()
+            .register_type::<SpeedBuff>()
+            .register_type::<Option<String>>()
+            .register_type::<ItemShape>()
+            .register_type::<Color>()
+            .register_type::<Vec<String>>()
+            .add_systems(
+                Update,
+                self::decay::decay_items.run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                SpawnScene,
+                (
+                    self::reload::reload_items_system.run_if(resource_exists::<ItemHotReload>()),
+                    self::reload::convert_items_system,
+                    self::spawn::spawn_items_system,
+                    self::spawn::spawn_requested_items,
+                    self::containter::init_item_containers,
+                    self::model::attach_item_visuals,
+                )
+                    .chain(),
+            )
             .add_systems(
                 PostUpdate,
                 (
                     self::containter::put_in_container,
                     self::containter::move_between,
-                    self::containter::take_out_of_container,
+                    self::stack::split_stack,
+                    self::stack::merge_stacks,
+                    self::stack::transfer_stack,
+                    self::transaction::apply_transactions,
+                    apply_deferred,
+                    self::change::container_change_events,
+                    update_container_index,
                 )
                     .chain(),
             );
+
+        #[cfg(feature = "hot_reload")]
+        app.init_resource::<ItemHotReload>();
     }
 }
@@ -1,21 +1,76 @@
+use super::{ItemIcon, ItemScene};
+use bevy::scene::Scene;
 use bevy::{
-    asset::{io::Reader, Asset, AssetLoader, AsyncReadExt, LoadContext},
+    asset::{
+        io::{Reader, Writer},
+        saver::{AssetSaver, SavedAsset},
+        Asset, AssetLoader, AssetPath, AsyncReadExt, AsyncWriteExt, Handle, LoadContext,
+        ReadAssetBytesError,
+    },
     ecs::reflect::AppTypeRegistry,
     ecs::world::{FromWorld, World},
     reflect::serde::{
-        TypeRegistrationDeserializer, TypedReflectDeserializer, UntypedReflectDeserializer,
+        TypedReflectDeserializer, TypedReflectSerializer, UntypedReflectDeserializer,
+    },
+    reflect::{
+        FromReflect, Reflect, ReflectFromReflect, TypePath, TypeRegistration, TypeRegistry,
+        TypeRegistryArc,
     },
-    reflect::{Reflect, TypePath, TypeRegistry, TypeRegistryArc},
+    render::texture::Image,
     utils::{
         thiserror::{self, Error},
         BoxedFuture, HashSet,
     },
 };
-use serde::de::{self, DeserializeSeed};
+use serde::{
+    de::{self, DeserializeSeed},
+    ser::{self, SerializeMap},
+    Deserialize, Serialize,
+};
+use std::any::TypeId;
 
-#[derive(Asset, TypePath, Debug)]
+#[derive(Asset, TypePath, Debug, Default)]
 pub struct ItemAsset {
     pub components: Vec<Box<dyn Reflect>>,
+    /// Loaded from the [`ItemScene`] path.
+    #[dependency]
+    pub scene: Option<Handle<Scene>>,
+    /// Loaded from the [`ItemIcon`] path.
+    #[dependency]
+    pub icon: Option<Handle<Image>>,
+}
+
+impl ItemAsset {
+    /// Returns the template value of the component `T` if the item declares it.
+    pub fn get<T: FromReflect + TypePath>(&self) -> Option<T> {
+        self.components.iter().find_map(|reflect| {
+            let type_info = reflect.get_represented_type_info()?;
+            if type_info.type_id() == TypeId::of::<T>() {
+                T::from_reflect(&**reflect)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Whether the item declares the component with the given type id.
+    pub fn contains(&self, type_id: TypeId) -> bool {
+        self.components.iter().any(|reflect| {
+            reflect
+                .get_represented_type_info()
+                .is_some_and(|info| info.type_id() == type_id)
+        })
+    }
+
+    /// Writes the item in the canonical form read by [`ItemAssetLoader`].
+    pub fn to_ron(&self, registry: &TypeRegistry) -> Result<String, ron::Error> {
+        ComponentsSerializer {
+            extends: None,
+            components: &self.components,
+            registry,
+        }
+        .to_ron()
+    }
 }
 
 pub struct ItemAssetLoader {
@@ -39,6 +94,12 @@ pub enum ItemAssetLoaderError {
     /// A [RON](ron) Error
     #[error("Could not parse RON: {0}")]
     RonSpannedError(#[from] ron::error::SpannedError),
+    /// Parent item could not be read
+    #[error("Could not read parent item: {0}")]
+    ReadParent(#[from] ReadAssetBytesError),
+    /// Parent item extends the item itself, directly or not
+    #[error("Cyclic inheritance through `{path}`")]
+    CyclicInheritance { path: String },
 }
 
 impl AssetLoader for ItemAssetLoader {
@@ -54,32 +115,205 @@ impl AssetLoader for ItemAssetLoader {
         &'a self,
         reader: &'a mut Reader,
         _: &'a Self::Settings,
-        _: &'a mut LoadContext,
+        load_context: &'a mut LoadContext,
     ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
         Box::pin(async move {
             let mut bytes = Vec::new();
             reader.read_to_end(&mut bytes).await?;
 
-            let mut deserializer = ron::de::Deserializer::from_bytes(&bytes)?;
-            let components = ComponentsDeserializer {
-                registry: &self.type_registry.read(),
+            let mut template = self.deserialize(&bytes)?;
+
+            // walk up the parents, each one being overridden by the templates below it
+            let mut visited = HashSet::new();
+            visited.insert(load_context.asset_path().clone_owned());
+
+            while let Some(parent) = template.extends.take() {
+                let path = AssetPath::parse(&parent).clone_owned();
+                if !visited.insert(path.clone()) {
+                    return Err(ItemAssetLoaderError::CyclicInheritance { path: parent });
+                }
+
+                let bytes = load_context.read_asset_bytes(path).await?;
+                let mut base = self.deserialize(&bytes)?;
+                base.merge(template);
+                template = base;
+            }
+
+            let mut asset = Self::Asset {
+                components: template.components,
+                ..Default::default()
             };
+            asset.scene = asset.get().map(|ItemScene(path)| load_context.load(path));
+            asset.icon = asset.get().map(|ItemIcon(path)| load_context.load(path));
+
+            Ok(asset)
+        })
+    }
+}
+
+impl ItemAssetLoader {
+    fn deserialize(&self, bytes: &[u8]) -> Result<ItemTemplate, ItemAssetLoaderError> {
+        let mut deserializer = ron::de::Deserializer::from_bytes(bytes)?;
+        let components = ComponentsDeserializer {
+            registry: &self.type_registry.read(),
+        };
+
+        Ok(components
+            .deserialize(&mut deserializer)
+            .map_err(|e| deserializer.span_error(e))?)
+    }
+}
+
+/// Map key naming the parent item of a template.
+pub const EXTENDS_KEY: &str = "extends";
 
-            Ok(Self::Asset {
-                components: components
-                    .deserialize(&mut deserializer)
-                    .map_err(|e| deserializer.span_error(e))?,
+/// Contents of a single `.item.ron` file before its parents are resolved.
+#[derive(Debug, Default)]
+pub struct ItemTemplate {
+    /// Asset path of the parent item, e.g. `items/potion.item.ron`.
+    pub extends: Option<String>,
+    pub components: Vec<Box<dyn Reflect>>,
+}
+
+impl ItemTemplate {
+    /// Overrides components of `self` with the ones declared by `child`.
+    pub fn merge(&mut self, child: Self) {
+        for reflect in child.components {
+            let type_id = reflect
+                .get_represented_type_info()
+                .map(|info| info.type_id());
+            let existing = self.components.iter_mut().find(|base| {
+                let base_id = base.get_represented_type_info().map(|info| info.type_id());
+                base_id.is_some() && base_id == type_id
+            });
+
+            match existing {
+                Some(base) => *base = reflect,
+                None => self.components.push(reflect),
+            }
+        }
+    }
+
+    /// Same parent and equal components, in any order.
+    pub fn reflect_eq(&self, other: &Self) -> bool {
+        self.extends == other.extends
+            && self.components.len() == other.components.len()
+            && self.components.iter().all(|reflect| {
+                other.components.iter().any(|other| {
+                    other.reflect_type_path() == reflect.reflect_type_path()
+                        && reflect.reflect_partial_eq(&**other).unwrap_or(false)
+                })
             })
+    }
+
+    /// Writes the template in the canonical form read by [`ComponentsDeserializer`].
+    pub fn to_ron(&self, registry: &TypeRegistry) -> Result<String, ron::Error> {
+        ComponentsSerializer {
+            extends: self.extends.as_deref(),
+            components: &self.components,
+            registry,
+        }
+        .to_ron()
+    }
+}
+
+pub struct ItemAssetSaver {
+    type_registry: TypeRegistryArc,
+}
+
+impl FromWorld for ItemAssetSaver {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            type_registry: world.resource::<AppTypeRegistry>().0.clone(),
+        }
+    }
+}
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum ItemAssetSaverError {
+    /// An [IO](std::io) Error
+    #[error("Could not save asset: {0}")]
+    Io(#[from] std::io::Error),
+    /// A [RON](ron) Error
+    #[error("Could not write RON: {0}")]
+    RonError(#[from] ron::Error),
+}
+
+impl AssetSaver for ItemAssetSaver {
+    type Asset = ItemAsset;
+    type Settings = ();
+    type OutputLoader = ItemAssetLoader;
+    type Error = ItemAssetSaverError;
+
+    fn save<'a>(
+        &'a self,
+        writer: &'a mut Writer,
+        asset: SavedAsset<'a, Self::Asset>,
+        _: &'a Self::Settings,
+    ) -> BoxedFuture<'a, Result<(), Self::Error>> {
+        Box::pin(async move {
+            let text = asset.to_ron(&self.type_registry.read())?;
+            writer.write_all(text.as_bytes()).await?;
+            Ok(())
         })
     }
 }
 
-struct ComponentsDeserializer<'a> {
-    registry: &'a TypeRegistry,
+/// Counterpart of [`ComponentsDeserializer`], components are sorted by type path.
+pub struct ComponentsSerializer<'a> {
+    pub extends: Option<&'a str>,
+    pub components: &'a [Box<dyn Reflect>],
+    pub registry: &'a TypeRegistry,
+}
+
+impl ComponentsSerializer<'_> {
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+    }
+}
+
+impl Serialize for ComponentsSerializer<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut components = Vec::with_capacity(self.components.len());
+        for reflect in self.components {
+            let type_info = reflect.get_represented_type_info().ok_or_else(|| {
+                ser::Error::custom(format_args!(
+                    "dynamic type `{}` without a represented type",
+                    reflect.reflect_type_path(),
+                ))
+            })?;
+            // dynamic enums read by the deserializer keep the first variant index
+            let concrete = self
+                .registry
+                .get_type_data::<ReflectFromReflect>(type_info.type_id())
+                .and_then(|from| from.from_reflect(&**reflect));
+            components.push((type_info.type_path(), &**reflect, concrete));
+        }
+        components.sort_by_key(|&(type_path, ..)| type_path);
+
+        let len = components.len() + usize::from(self.extends.is_some());
+        let mut map = serializer.serialize_map(Some(len))?;
+        if let Some(extends) = self.extends {
+            map.serialize_entry(EXTENDS_KEY, extends)?;
+        }
+        for (type_path, reflect, concrete) in &components {
+            let reflect = concrete.as_deref().unwrap_or(*reflect);
+            map.serialize_entry(
+                type_path,
+                &TypedReflectSerializer::new(reflect, self.registry),
+            )?;
+        }
+        map.end()
+    }
+}
+
+pub struct ComponentsDeserializer<'a> {
+    pub registry: &'a TypeRegistry,
 }
 
 impl<'a, 'de> de::DeserializeSeed<'de> for ComponentsDeserializer<'a> {
-    type Value = Vec<Box<dyn Reflect>>;
+    type Value = ItemTemplate;
 
     fn deserialize<D: serde::Deserializer<'de>>(self, de: D) -> Result<Self::Value, D::Error> {
         de.deserialize_map(self)
@@ -87,7 +321,7 @@ impl<'a, 'de> de::DeserializeSeed<'de> for ComponentsDeserializer<'a> {
 }
 
 impl<'a, 'de> de::Visitor<'de> for ComponentsDeserializer<'a> {
-    type Value = Vec<Box<dyn Reflect>>;
+    type Value = ItemTemplate;
 
     fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
         formatter.write_str("map of reflect types")
@@ -95,10 +329,21 @@ impl<'a, 'de> de::Visitor<'de> for ComponentsDeserializer<'a> {
 
     fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
         let mut added = HashSet::new();
-        let mut entries = Vec::new();
-        while let Some(registration) =
-            map.next_key_seed(TypeRegistrationDeserializer::new(self.registry))?
-        {
+        let mut template = ItemTemplate::default();
+        while let Some(key) = map.next_key_seed(ItemKeyDeserializer {
+            registry: self.registry,
+        })? {
+            let registration = match key {
+                ItemKey::Extends if template.extends.is_some() => {
+                    return Err(de::Error::duplicate_field(EXTENDS_KEY));
+                }
+                ItemKey::Extends => {
+                    template.extends = Some(map.next_value()?);
+                    continue;
+                }
+                ItemKey::Component(registration) => registration,
+            };
+
             if !added.insert(registration.type_id()) {
                 return Err(de::Error::custom(format_args!(
                     "duplicate reflect type: `{}`",
@@ -107,18 +352,127 @@ impl<'a, 'de> de::Visitor<'de> for ComponentsDeserializer<'a> {
             }
 
             let seed = TypedReflectDeserializer::new(registration, self.registry);
-            entries.push(map.next_value_seed(seed)?);
+            template.components.push(map.next_value_seed(seed)?);
         }
-        Ok(entries)
+        Ok(template)
     }
 
     fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
-        let mut dynamic_properties = Vec::new();
+        let mut template = ItemTemplate::default();
         while let Some(entity) =
             seq.next_element_seed(UntypedReflectDeserializer::new(self.registry))?
         {
-            dynamic_properties.push(entity);
+            template.components.push(entity);
         }
-        Ok(dynamic_properties)
+        Ok(template)
+    }
+}
+
+enum ItemKey<'a> {
+    Extends,
+    Component(&'a TypeRegistration),
+}
+
+struct ItemKeyDeserializer<'a> {
+    registry: &'a TypeRegistry,
+}
+
+impl<'a, 'de> de::DeserializeSeed<'de> for ItemKeyDeserializer<'a> {
+    type Value = ItemKey<'a>;
+
+    fn deserialize<D: serde::Deserializer<'de>>(self, de: D) -> Result<Self::Value, D::Error> {
+        let key = String::deserialize(de)?;
+        if key == EXTENDS_KEY {
+            return Ok(ItemKey::Extends);
+        }
+
+        self.registry
+            .get_with_type_path(&key)
+            .map(ItemKey::Component)
+            .ok_or_else(|| de::Error::custom(format_args!("no registration found for `{}`", key)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{loading::ItemDatabase, mechanics::item::ItemPlugin};
+    use bevy::{asset::LoadState, prelude::*};
+    use std::{path::Path, time::Duration};
+
+    #[test]
+    fn items_round_trip() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default(), ItemPlugin));
+        let loader = ItemAssetLoader::from_world(&mut app.world);
+        let registry = app.world.resource::<AppTypeRegistry>().read();
+
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("assets")
+            .join(ItemDatabase::FOLDER);
+        let mut checked = 0;
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if !path.to_string_lossy().ends_with(ItemDatabase::EXTENSION) {
+                continue;
+            }
+
+            let original = loader.deserialize(&std::fs::read(&path).unwrap()).unwrap();
+            let saved = original.to_ron(&registry).unwrap();
+            let reloaded = loader.deserialize(saved.as_bytes()).unwrap();
+            assert!(
+                reloaded.reflect_eq(&original),
+                "{} reads back as:\n{}",
+                path.display(),
+                saved
+            );
+            checked += 1;
+        }
+        assert!(checked > 0);
+    }
+
+    #[test]
+    fn cyclic_parents_fail_to_load() {
+        let dir = std::env::temp_dir().join(format!("lifer-items-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let files = [
+            ("a.item.ron", r#"{ "extends": "b.item.ron" }"#),
+            ("b.item.ron", r#"{ "extends": "a.item.ron" }"#),
+            ("base.item.ron", "{}"),
+            ("child.item.ron", r#"{ "extends": "base.item.ron" }"#),
+        ];
+        for (file, text) in files {
+            std::fs::write(dir.join(file), text).unwrap();
+        }
+
+        let mut app = App::new();
+        let assets = AssetPlugin {
+            file_path: dir.to_string_lossy().into_owned(),
+            ..default()
+        };
+        app.add_plugins((MinimalPlugins, assets))
+            .init_asset::<ItemAsset>()
+            .init_asset_loader::<ItemAssetLoader>();
+
+        let server = app.world.resource::<AssetServer>().clone();
+        let cyclic: Handle<ItemAsset> = server.load("a.item.ron");
+        let child: Handle<ItemAsset> = server.load("child.item.ron");
+
+        let done = |handle: &Handle<ItemAsset>| {
+            let state = server.get_load_state(handle);
+            matches!(state, Some(LoadState::Loaded | LoadState::Failed))
+        };
+        for _ in 0..1000 {
+            app.update();
+            if done(&cyclic) && done(&child) {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(server.get_load_state(&cyclic), Some(LoadState::Failed));
+        // a parent which does not lead back loads fine
+        assert_eq!(server.get_load_state(&child), Some(LoadState::Loaded));
     }
 }
@@ -0,0 +1,251 @@
+use super::{Consumable, ItemAsset};
+use bevy::ecs::system::{lifetimeless::Read, SystemParam};
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+/// Limits of a [`Container`](super::Container), unlimited by default.
+#[derive(Component, Clone, Copy, Debug, Reflect)]
+#[reflect(Component)]
+pub struct ContainerCapacity {
+    pub slots: usize,
+    pub weight: f32,
+    pub volume: f32,
+}
+
+impl Default for ContainerCapacity {
+    fn default() -> Self {
+        Self::UNLIMITED
+    }
+}
+
+impl ContainerCapacity {
+    pub const UNLIMITED: Self = Self {
+        slots: usize::MAX,
+        weight: f32::INFINITY,
+        volume: f32::INFINITY,
+    };
+
+    /// How many `units` of an item fit next to `load`.
+    pub fn fit(
+        &self,
+        load: &ContainerLoad,
+        size: &ItemSize,
+        new_slot: bool,
+    ) -> (f32, Option<Overfill>) {
+        if new_slot && load.slots >= self.slots {
+            return (0.0, Some(Overfill::Slots));
+        }
+
+        let mut units = size.units;
+        let mut reason = None;
+
+        let by_weight = per_unit_room(self.weight - load.weight, size.weight);
+        if by_weight < units {
+            units = by_weight;
+            reason = Some(Overfill::Weight);
+        }
+
+        let by_volume = per_unit_room(self.volume - load.volume, size.volume);
+        if by_volume < units {
+            units = by_volume;
+            reason = Some(Overfill::Volume);
+        }
+
+        (units, reason)
+    }
+}
+
+fn per_unit_room(room: f32, per_unit: f32) -> f32 {
+    if per_unit > 0.0 {
+        (room / per_unit).max(0.0)
+    } else {
+        f32::INFINITY
+    }
+}
+
+/// Which limit of a [`ContainerCapacity`] was hit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overfill {
+    Slots,
+    Weight,
+    Volume,
+}
+
+/// Weight of a single unit of an item.
+#[derive(Component, Clone, Copy, Debug, Default, Reflect)]
+#[reflect(Component)]
+pub struct ItemWeight(pub f32);
+
+/// Volume of a single unit of an item.
+#[derive(Component, Clone, Copy, Debug, Default, Reflect)]
+#[reflect(Component)]
+pub struct ItemVolume(pub f32);
+
+/// What an item takes from a container.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ItemSize {
+    /// Stack amount, `1.0` for items which are not [`Consumable`].
+    pub units: f32,
+    pub weight: f32,
+    pub volume: f32,
+}
+
+impl ItemSize {
+    /// Size of a single unit as declared by the item template.
+    pub fn unit_of(asset: &ItemAsset) -> Self {
+        Self {
+            units: 1.0,
+            weight: asset.get::<ItemWeight>().map_or(0.0, |w| w.0),
+            volume: asset.get::<ItemVolume>().map_or(0.0, |v| v.0),
+        }
+    }
+
+    pub fn total_weight(&self) -> f32 {
+        self.units * self.weight
+    }
+
+    pub fn total_volume(&self) -> f32 {
+        self.units * self.volume
+    }
+}
+
+/// What is already in a container.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ContainerLoad {
+    pub slots: usize,
+    pub weight: f32,
+    pub volume: f32,
+}
+
+impl ContainerLoad {
+    pub fn add(&mut self, size: &ItemSize, new_slot: bool) {
+        self.slots += usize::from(new_slot);
+        self.weight += size.total_weight();
+        self.volume += size.total_volume();
+    }
+}
+
+pub type ItemSizeQuery = (
+    Option<Read<Consumable>>,
+    Option<Read<ItemWeight>>,
+    Option<Read<ItemVolume>>,
+);
+
+#[derive(SystemParam)]
+pub struct ReadCapacity<'w, 's> {
+    assets: Res<'w, Assets<ItemAsset>>,
+    containers: Query<'w, 's, (Read<ContainerCapacity>, Option<Read<Children>>)>,
+    items: Query<'w, 's, ItemSizeQuery, With<Handle<ItemAsset>>>,
+    parents: Query<'w, 's, Read<Parent>>,
+}
+
+impl ReadCapacity<'_, '_> {
+    /// Size of a single unit of the item.
+    pub fn unit(&self, item: &Handle<ItemAsset>) -> ItemSize {
+        self.assets
+            .get(item)
+            .map(ItemSize::unit_of)
+            .unwrap_or_default()
+    }
+
+    /// Size of the item, a container item carries the load of its contents.
+    pub fn size(&self, item: Entity) -> ItemSize {
+        let (consumable, weight, volume) = self.items.get(item).unwrap_or_default();
+        let mut size = ItemSize {
+            units: consumable.map_or(1.0, |c| c.current.max(0.0)),
+            weight: weight.map_or(0.0, |w| w.0),
+            volume: volume.map_or(0.0, |v| v.0),
+        };
+
+        if let Some((_, contents)) = self.load(item) {
+            size.weight += contents.weight;
+            size.volume += contents.volume;
+        }
+
+        size
+    }
+
+    /// Capacity and current load, `None` for unlimited containers.
+    pub fn load(&self, container: Entity) -> Option<(ContainerCapacity, ContainerLoad)> {
+        let (capacity, children) = self.containers.get(container).ok()?;
+        let mut load = ContainerLoad::default();
+        // a scene spawned under a container item is not part of its contents
+        for &child in children.into_iter().flatten() {
+            if self.items.contains(child) {
+                load.add(&self.size(child), true);
+            }
+        }
+        Some((*capacity, load))
+    }
+
+    /// `to` and the containers holding it, without those `item` already is in.
+    pub fn enclosing(&self, to: Entity, item: Entity) -> Vec<Entity> {
+        let carried: Vec<Entity> = self.parents.iter_ancestors(item).collect();
+        std::iter::once(to)
+            .chain(self.parents.iter_ancestors(to))
+            .filter(|container| !carried.contains(container))
+            .collect()
+    }
+
+    /// How many more units of the item fit in the container.
+    pub fn room_for(&self, container: Entity, item: &Handle<ItemAsset>, new_slot: bool) -> f32 {
+        let size = ItemSize {
+            units: f32::INFINITY,
+            ..self.unit(item)
+        };
+        self.load(container)
+            .map_or(f32::INFINITY, |(capacity, load)| {
+                capacity.fit(&load, &size, new_slot).0
+            })
+    }
+}
+
+/// Loads of containers while items are put in one after another, read on first use.
+#[derive(Default, Debug)]
+pub struct Loads(HashMap<Entity, Option<(ContainerCapacity, ContainerLoad)>>);
+
+impl Loads {
+    /// Forgets the cached loads.
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    /// How many units of `size` fit in every one of `containers`,
+    /// only the first one taking a slot when `new_slot`.
+    pub fn fit(
+        &mut self,
+        capacity: &ReadCapacity,
+        containers: &[Entity],
+        size: &ItemSize,
+        new_slot: bool,
+    ) -> (f32, Option<Overfill>) {
+        let mut units = size.units;
+        let mut reason = None;
+        for (i, &container) in containers.iter().enumerate() {
+            let entry = self
+                .0
+                .entry(container)
+                .or_insert_with(|| capacity.load(container));
+            let Some((limits, load)) = entry else {
+                continue;
+            };
+
+            let size = ItemSize { units, ..*size };
+            let (fit, overfill) = limits.fit(load, &size, new_slot && i == 0);
+            if overfill.is_some() {
+                units = fit;
+                reason = overfill;
+            }
+        }
+        (units, reason)
+    }
+
+    /// Counts `size` in the loads of `containers`, as [`Self::fit`] checked it.
+    pub fn add(&mut self, containers: &[Entity], size: &ItemSize, new_slot: bool) {
+        for (i, container) in containers.iter().enumerate() {
+            if let Some(Some((_, load))) = self.0.get_mut(container) {
+                load.add(size, new_slot && i == 0);
+            }
+        }
+    }
+}
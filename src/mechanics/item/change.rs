@@ -0,0 +1,132 @@
+use super::{Consumable, Container, ItemAsset};
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+/// An item entered a container.
+#[derive(Event, Clone, Debug)]
+pub struct ItemAdded {
+    pub container: Entity,
+    pub item: Entity,
+    pub asset: Handle<ItemAsset>,
+}
+
+/// An item left a container, moved elsewhere or despawned.
+#[derive(Event, Clone, Debug)]
+pub struct ItemRemoved {
+    pub container: Entity,
+    pub item: Entity,
+    pub asset: Handle<ItemAsset>,
+}
+
+/// The [`Consumable`] amount of an item in a container changed.
+#[derive(Event, Clone, Debug)]
+pub struct ItemQuantityChanged {
+    pub container: Entity,
+    pub item: Entity,
+    pub asset: Handle<ItemAsset>,
+    pub previous: f32,
+    pub current: f32,
+}
+
+/// What was last reported about an item in a container.
+pub struct Contained {
+    container: Entity,
+    asset: Handle<ItemAsset>,
+    quantity: Option<f32>,
+}
+
+pub type ContainedItemQuery<'a> = (
+    Entity,
+    &'a Parent,
+    &'a Handle<ItemAsset>,
+    Option<&'a Consumable>,
+);
+
+/// Moved to another container or turned into another item.
+pub type ContainedItemChanged = Or<(Changed<Parent>, Changed<Handle<ItemAsset>>)>;
+
+/// Reports what happened to container contents since the last run.
+///
+/// Runs after the container systems, so it also picks up [`Consumable`] writes
+/// made through [`WriteContainer`](super::WriteContainer) during the frame.
+#[allow(clippy::too_many_arguments)]
+pub fn container_change_events(
+    mut contained: Local<HashMap<Entity, Contained>>,
+    mut removed: RemovedComponents<Parent>,
+    moved: Query<ContainedItemQuery, ContainedItemChanged>,
+    changed: Query<(Entity, &Consumable), Changed<Consumable>>,
+    parents: Query<&Parent>,
+    containers: Query<(), With<Container>>,
+
+    mut added_events: EventWriter<ItemAdded>,
+    mut removed_events: EventWriter<ItemRemoved>,
+    mut changed_events: EventWriter<ItemQuantityChanged>,
+) {
+    let mut remove = |item: Entity, contained: &mut HashMap<Entity, Contained>| {
+        if let Some(Contained {
+            container, asset, ..
+        }) = contained.remove(&item)
+        {
+            removed_events.send(ItemRemoved {
+                container,
+                item,
+                asset,
+            });
+        }
+    };
+
+    for item in removed.read() {
+        // taken out, or despawned together with the item
+        if parents.get(item).is_err() {
+            remove(item, &mut contained);
+        }
+    }
+
+    for (item, parent, asset, consumable) in &moved {
+        let container = parent.get();
+        let same = contained
+            .get(&item)
+            .is_some_and(|c| c.container == container && c.asset == *asset);
+        if same {
+            continue;
+        }
+
+        remove(item, &mut contained);
+
+        if containers.contains(container) {
+            contained.insert(
+                item,
+                Contained {
+                    container,
+                    asset: asset.clone(),
+                    quantity: consumable.map(|c| c.current),
+                },
+            );
+            added_events.send(ItemAdded {
+                container,
+                item,
+                asset: asset.clone(),
+            });
+        }
+    }
+
+    for (item, consumable) in &changed {
+        let Some(entry) = contained.get_mut(&item) else {
+            continue;
+        };
+
+        let previous = entry.quantity.unwrap_or_default();
+        if entry.quantity == Some(consumable.current) {
+            continue;
+        }
+        entry.quantity = Some(consumable.current);
+
+        changed_events.send(ItemQuantityChanged {
+            container: entry.container,
+            item,
+            asset: entry.asset.clone(),
+            previous,
+            current: consumable.current,
+        });
+    }
+}
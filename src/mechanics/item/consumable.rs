@@ -1,9 +1,9 @@
-use super::{ReadContainer, WriteContainer};
+use super::{ReadContainer, ReflectReloadItem, ReloadItem, WriteContainer};
 use bevy::ecs::system::lifetimeless::{Read, Write};
 use bevy::prelude::*;
 
-#[derive(Component, Default, Reflect)]
-#[reflect(Component)]
+#[derive(Component, Clone, Debug, Default, Reflect)]
+#[reflect(Component, ReloadItem)]
 pub struct Consumable {
     pub current: f32,
     pub maximum: f32,
@@ -23,6 +23,40 @@ impl Consumable {
         self.current = 0.0;
         value
     }
+
+    /// Room left before the stack is full.
+    pub fn space(&self) -> f32 {
+        (self.maximum - self.current).max(0.0)
+    }
+
+    /// Takes up to `amount` units, returns the taken amount.
+    pub fn take_amount(&mut self, amount: f32) -> f32 {
+        let value = amount.clamp(0.0, self.current.max(0.0));
+        self.current -= value;
+        value
+    }
+
+    /// Adds up to `amount` units, returns what did not fit.
+    pub fn fill(&mut self, amount: f32) -> f32 {
+        let value = amount.clamp(0.0, self.space());
+        self.current += value;
+        amount - value
+    }
+
+    /// Moves as much of `other` as fits into `self`, returns the moved amount.
+    pub fn merge(&mut self, other: &mut Self) -> f32 {
+        let value = other.current.clamp(0.0, self.space());
+        other.current -= value;
+        self.current += value;
+        value
+    }
+}
+
+impl ReloadItem for Consumable {
+    fn reload(&mut self, template: &Self) {
+        self.maximum = template.maximum;
+        self.current = self.current.min(self.maximum);
+    }
 }
 
 pub type ReadConsumable<'w, 's> = ReadContainer<'w, 's, Read<Consumable>>;
@@ -1,9 +1,11 @@
-use super::ItemAsset;
+use super::capacity::{ContainerCapacity, ItemSize, Loads, Overfill, ReadCapacity};
+use super::{Consumable, ContainerIndex, Item, ItemAsset, ItemTags, Perishable};
 use bevy::ecs::{
     query::{ReadOnlyWorldQuery, WorldQuery},
-    system::{lifetimeless::Read, SystemParam},
+    system::{lifetimeless::Read, EntityCommands, SystemParam},
 };
 use bevy::prelude::*;
+use std::any::TypeId;
 
 #[derive(Component, Clone, Copy, Debug)]
 pub struct Container;
@@ -11,6 +13,8 @@ pub struct Container;
 #[derive(Bundle)]
 pub struct ContainerBundle {
     pub container: Container,
+    pub capacity: ContainerCapacity,
+    pub index: ContainerIndex,
 
     /// The visibility of the entity.
     pub visibility: Visibility,
@@ -29,6 +33,8 @@ impl Default for ContainerBundle {
     fn default() -> Self {
         Self {
             container: Container,
+            capacity: ContainerCapacity::UNLIMITED,
+            index: ContainerIndex::default(),
 
             visibility: Visibility::Hidden,
             inherited_visibility: InheritedVisibility::HIDDEN,
@@ -40,15 +46,175 @@ impl Default for ContainerBundle {
     }
 }
 
+/// Sent when an item, or the part of a stack that did not fit, stays out of a container.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ContainerRejected {
+    /// The item left outside, for a partially accepted stack that is the split off rest.
+    pub item: Entity,
+    pub container: Entity,
+    /// Rejected units, `1.0` for items which are not [`Consumable`].
+    pub amount: f32,
+    pub reason: Overfill,
+}
+
+type AdmittedStack = (
+    Read<Handle<ItemAsset>>,
+    Read<Consumable>,
+    Option<Read<Perishable>>,
+);
+
+/// Checks items against [`ContainerCapacity`] before they are put in.
+#[derive(SystemParam)]
+pub struct Admission<'w, 's> {
+    commands: Commands<'w, 's>,
+    capacity: ReadCapacity<'w, 's>,
+    stacks: Query<'w, 's, AdmittedStack>,
+    parents: Query<'w, 's, Read<Parent>>,
+    rejected: EventWriter<'w, ContainerRejected>,
+    loads: Local<'s, Loads>,
+}
+
+impl Admission<'_, '_> {
+    /// Returns whether `item` goes into `to`, and into every container holding `to`.
+    ///
+    /// A stack that fits only partially is split, the rest is spawned and handed to `place_rest`.
+    pub fn admit(
+        &mut self,
+        item: Entity,
+        to: Entity,
+        place_rest: impl FnOnce(&mut EntityCommands),
+    ) -> bool {
+        if to == item || self.parents.iter_ancestors(to).any(|parent| parent == item) {
+            warn!("{:?} can not be put inside itself", item);
+            return false;
+        }
+
+        // the containers around `to` carry the item too, except those it is already in
+        let containers = self.capacity.enclosing(to, item);
+        let size = self.capacity.size(item);
+        let (units, reason) = self.loads.fit(&self.capacity, &containers, &size, true);
+
+        let Some(reason) = reason else {
+            self.loads.add(&containers, &size, true);
+            return true;
+        };
+
+        let stack = self.stacks.get(item).ok().filter(|_| units > 0.0);
+        let Some((asset, stack, perishable)) = stack else {
+            self.rejected.send(ContainerRejected {
+                item,
+                container: to,
+                amount: size.units,
+                reason,
+            });
+            return false;
+        };
+
+        let rest = Consumable {
+            current: stack.current - units,
+            maximum: stack.maximum,
+        };
+        let amount = rest.current;
+
+        self.commands.entity(item).insert(Consumable {
+            current: units,
+            maximum: stack.maximum,
+        });
+
+        let mut rest = self.commands.spawn((asset.clone(), rest));
+        if let Some(perishable) = perishable {
+            rest.insert(perishable.clone());
+        }
+        place_rest(&mut rest);
+        let rest = rest.id();
+
+        self.loads
+            .add(&containers, &ItemSize { units, ..size }, true);
+
+        self.rejected.send(ContainerRejected {
+            item: rest,
+            container: to,
+            amount,
+            reason,
+        });
+
+        true
+    }
+
+    /// How many `units` of the stack `item` fit in `to` and the containers around it,
+    /// counting them as taken. Only a new stack takes a slot.
+    pub fn reserve(
+        &mut self,
+        item: Entity,
+        units: f32,
+        to: Entity,
+        new_slot: bool,
+    ) -> (f32, Option<Overfill>) {
+        let containers = self.capacity.enclosing(to, item);
+        let size = ItemSize {
+            units,
+            ..self.capacity.size(item)
+        };
+        let (fit, reason) = self.loads.fit(&self.capacity, &containers, &size, new_slot);
+
+        let fitting = ItemSize { units: fit, ..size };
+        self.loads.add(&containers, &fitting, new_slot && fit > 0.0);
+        (fit, reason)
+    }
+
+    /// Reports `amount` units of `item` which stay out of `container`.
+    pub fn reject(&mut self, item: Entity, container: Entity, amount: f32, reason: Overfill) {
+        self.rejected.send(ContainerRejected {
+            item,
+            container,
+            amount,
+            reason,
+        });
+    }
+
+    /// Forgets the loads cached by earlier admissions, call once per system run.
+    pub fn clear(&mut self) {
+        self.loads.clear();
+    }
+}
+
+/// Items which are not yet set up to hold other items.
+pub type NewContainerItem = (With<Item>, With<ContainerCapacity>, Without<Container>);
+
+/// Lets items whose template has a [`ContainerCapacity`] hold other items, like a bag.
+pub fn init_item_containers(mut commands: Commands, query: Query<Entity, NewContainerItem>) {
+    for entity in &query {
+        commands
+            .entity(entity)
+            .insert((Container, ContainerIndex::default()));
+    }
+}
+
 #[derive(Event, Clone, Copy, Debug)]
 pub struct PutInContainer {
     pub item: Entity,
     pub to: Entity,
 }
 
-pub fn put_in_container(mut commands: Commands, mut events: ResMut<Events<PutInContainer>>) {
+pub fn put_in_container(
+    mut commands: Commands,
+    mut events: ResMut<Events<PutInContainer>>,
+    parents: Query<&Parent>,
+    mut admission: Admission,
+) {
+    admission.clear();
     for PutInContainer { item, to } in events.drain() {
-        commands.entity(to).add_child(item);
+        // the rest of a partially fitting stack stays with the parent of the item,
+        // a loose item leaves it to whoever handles the rejection
+        let parent = parents.get(item).ok().map(Parent::get);
+        let admitted = admission.admit(item, to, |rest| {
+            if let Some(parent) = parent {
+                rest.set_parent(parent);
+            }
+        });
+        if admitted {
+            commands.entity(to).add_child(item);
+        }
     }
 }
 
@@ -59,113 +225,452 @@ pub struct MoveBetweenContainers {
     pub to: Entity,
 }
 
-pub fn move_between(mut commands: Commands, mut events: ResMut<Events<MoveBetweenContainers>>) {
+pub fn move_between(
+    mut commands: Commands,
+    mut events: ResMut<Events<MoveBetweenContainers>>,
+    mut admission: Admission,
+) {
+    admission.clear();
     for MoveBetweenContainers { item, from, to } in events.drain() {
-        commands.entity(to).add_child(item);
-        commands.entity(from).remove_children(&[item]);
+        // the rest of a partially fitting stack stays where it was
+        let admitted = admission.admit(item, to, |rest| {
+            rest.set_parent(from);
+        });
+        if admitted {
+            commands.entity(to).add_child(item);
+            commands.entity(from).remove_children(&[item]);
+        }
     }
 }
 
+/// Takes the item out of `from`, it lands on the ground where the container is.
+///
+/// Handled together with [`DropItem`](crate::mechanics::DropItem) by the ground plugin.
 #[derive(Event, Clone, Copy, Debug)]
 pub struct TakeOutOfContainer {
     pub item: Entity,
     pub from: Entity,
 }
 
-pub fn take_out_of_container(
-    mut commands: Commands,
-    mut events: ResMut<Events<TakeOutOfContainer>>,
-) {
-    for TakeOutOfContainer { item, from } in events.drain() {
-        commands.entity(from).remove_children(&[item]);
+/// Which items of a container a lookup is about.
+#[derive(Clone, Copy, Debug)]
+pub enum ItemFilter<'a> {
+    /// Items of exactly this asset.
+    Asset(AssetId<ItemAsset>),
+    /// Items listing the tag in their [`ItemTags`].
+    Tag(&'a str),
+    /// Items whose template declares the component.
+    Component(TypeId),
+}
+
+impl ItemFilter<'_> {
+    pub fn component<T: Component>() -> Self {
+        Self::Component(TypeId::of::<T>())
+    }
+}
+
+impl From<&Handle<ItemAsset>> for ItemFilter<'_> {
+    fn from(handle: &Handle<ItemAsset>) -> Self {
+        Self::Asset(handle.id())
+    }
+}
+
+/// Tells whether the items of an asset pass an [`ItemFilter`].
+#[derive(SystemParam)]
+pub struct ItemMatcher<'w, 's> {
+    assets: Res<'w, Assets<ItemAsset>>,
+    tags: Query<'w, 's, Read<ItemTags>>,
+}
+
+impl ItemMatcher<'_, '_> {
+    /// `item` is any spawned item of the `asset`, tags are read from it.
+    pub fn matches(&self, filter: ItemFilter, asset: AssetId<ItemAsset>, item: Entity) -> bool {
+        match filter {
+            ItemFilter::Asset(id) => id == asset,
+            ItemFilter::Tag(tag) => self.tags.get(item).is_ok_and(|tags| tags.contains(tag)),
+            ItemFilter::Component(type_id) => self
+                .assets
+                .get(asset)
+                .is_some_and(|asset| asset.contains(type_id)),
+        }
+    }
+
+    fn reborrow(&self) -> ItemMatcher<'_, '_> {
+        ItemMatcher {
+            assets: Res::clone(&self.assets),
+            tags: self.tags.to_readonly(),
+        }
     }
 }
 
+/// Looks up items in a container through its [`ContainerIndex`].
 #[derive(SystemParam)]
 pub struct ReadContainer<'w, 's, Q: ReadOnlyWorldQuery + 'static> {
+    pub index: Query<'w, 's, Read<ContainerIndex>>,
     pub query: Query<'w, 's, (Read<Handle<ItemAsset>>, Q)>,
+    pub matcher: ItemMatcher<'w, 's>,
 }
 
 impl<Q: ReadOnlyWorldQuery + 'static> ReadContainer<'_, '_, Q> {
     pub fn get<R>(
         &self,
         item: &Handle<ItemAsset>,
-        container: Option<&Children>,
+        container: Entity,
         map: impl FnOnce(Q::Item<'_>) -> R,
     ) -> Option<R> {
-        for &child_entity in container? {
-            if let Ok((asset, consumable)) = self.query.get(child_entity) {
-                if asset == item {
-                    return Some(map(consumable));
-                }
-            }
-        }
-        None
+        let entity = find(&self.index, &self.query.to_readonly(), item, container)?;
+        self.query.get(entity).ok().map(|(_, q)| map(q))
     }
 
     pub fn get_or<R>(
         &self,
         item: &Handle<ItemAsset>,
-        container: Option<&Children>,
+        container: Entity,
         default: R,
         map: impl FnOnce(Q::Item<'_>) -> R,
     ) -> R {
         self.get(item, container, map).unwrap_or(default)
     }
+
+    /// Cached units of the item in the container, as of the end of the last frame.
+    pub fn total(&self, item: &Handle<ItemAsset>, container: Entity) -> f32 {
+        self.index
+            .get(container)
+            .map_or(0.0, |index| index.total(item))
+    }
+
+    /// Items passing the filter, grouped by asset.
+    pub fn iter<'a>(
+        &'a self,
+        filter: ItemFilter<'a>,
+        container: Entity,
+    ) -> impl Iterator<Item = (&'a Handle<ItemAsset>, Q::Item<'a>)> + 'a {
+        self.entities(filter, container)
+            .filter_map(|entity| self.query.get(entity).ok())
+    }
+
+    /// First item passing the filter.
+    pub fn find<R>(
+        &self,
+        filter: ItemFilter,
+        container: Entity,
+        map: impl FnOnce(Q::Item<'_>) -> R,
+    ) -> Option<R> {
+        self.iter(filter, container).next().map(|(_, q)| map(q))
+    }
+
+    /// Cached units of all items passing the filter, see [`Self::total`].
+    pub fn sum(&self, filter: ItemFilter, container: Entity) -> f32 {
+        let Ok(index) = self.index.get(container) else {
+            return 0.0;
+        };
+
+        index
+            .assets()
+            .filter(|&asset| {
+                let item = index.items(asset).next();
+                item.is_some_and(|item| self.matcher.matches(filter, asset, item))
+            })
+            .fold(0.0, |sum, asset| sum + index.total(asset))
+    }
+
+    /// The container and the container items inside it at any depth, outermost first.
+    pub fn containers(&self, container: Entity) -> Vec<Entity> {
+        let mut containers = vec![container];
+        let mut next = 0;
+
+        while let Some(&current) = containers.get(next) {
+            next += 1;
+
+            let Ok(index) = self.index.get(current) else {
+                continue;
+            };
+            for asset in index.assets() {
+                for item in index.items(asset) {
+                    if self.index.contains(item) && !containers.contains(&item) {
+                        containers.push(item);
+                    }
+                }
+            }
+        }
+
+        containers
+    }
+
+    /// Items passing the filter, also inside nested containers.
+    pub fn iter_recursive<'a>(
+        &'a self,
+        filter: ItemFilter<'a>,
+        container: Entity,
+    ) -> impl Iterator<Item = (&'a Handle<ItemAsset>, Q::Item<'a>)> + 'a {
+        self.containers(container)
+            .into_iter()
+            .flat_map(move |container| self.iter(filter, container))
+    }
+
+    /// First item passing the filter, also inside nested containers.
+    pub fn find_recursive<R>(
+        &self,
+        filter: ItemFilter,
+        container: Entity,
+        map: impl FnOnce(Q::Item<'_>) -> R,
+    ) -> Option<R> {
+        self.iter_recursive(filter, container)
+            .next()
+            .map(|(_, q)| map(q))
+    }
+
+    /// Cached units of all items passing the filter, also inside nested containers.
+    pub fn sum_recursive(&self, filter: ItemFilter, container: Entity) -> f32 {
+        self.containers(container)
+            .into_iter()
+            .fold(0.0, |sum, container| sum + self.sum(filter, container))
+    }
+
+    fn entities_recursive<'a>(
+        &'a self,
+        filter: ItemFilter<'a>,
+        container: Entity,
+    ) -> impl Iterator<Item = Entity> + 'a {
+        self.containers(container)
+            .into_iter()
+            .flat_map(move |container| self.entities(filter, container))
+    }
+
+    fn entities<'a>(
+        &'a self,
+        filter: ItemFilter<'a>,
+        container: Entity,
+    ) -> impl Iterator<Item = Entity> + 'a {
+        let index = self.index.get(container).ok();
+        index.into_iter().flat_map(move |index| {
+            index
+                .assets()
+                .filter(move |&asset| {
+                    let item = index.items(asset).next();
+                    item.is_some_and(|item| self.matcher.matches(filter, asset, item))
+                })
+                .flat_map(move |asset| {
+                    // the index lags behind moves made this frame
+                    index.items(asset).filter(move |&entity| {
+                        self.query
+                            .get(entity)
+                            .is_ok_and(|(handle, _)| handle.id() == asset)
+                    })
+                })
+        })
+    }
 }
 
 #[derive(SystemParam)]
 pub struct WriteContainer<'w, 's, Q: WorldQuery + 'static> {
+    pub index: Query<'w, 's, Read<ContainerIndex>>,
     pub query: Query<'w, 's, (Read<Handle<ItemAsset>>, Q)>,
+    pub matcher: ItemMatcher<'w, 's>,
 }
 
 impl<'w, 's, Q: WorldQuery + 'static> WriteContainer<'w, 's, Q> {
-    pub fn to_readonly(&self) -> ReadContainer<'_, 's, Q::ReadOnly> {
+    pub fn to_readonly(&self) -> ReadContainer<'_, '_, Q::ReadOnly> {
         ReadContainer {
+            index: self.index.to_readonly(),
             query: self.query.to_readonly(),
+            matcher: self.matcher.reborrow(),
         }
     }
 
     pub fn get<R>(
         &mut self,
-        container: Option<&Children>,
+        container: Entity,
         item: &Handle<ItemAsset>,
         map: impl FnOnce(Q::Item<'_>) -> R,
     ) -> Option<R> {
-        for &child_entity in container? {
-            if let Ok((asset, consumable)) = self.query.get_mut(child_entity) {
-                if asset == item {
-                    return Some(map(consumable));
-                }
-            }
-        }
-        None
+        let entity = find(&self.index, &self.query.to_readonly(), item, container)?;
+        self.query.get_mut(entity).ok().map(|(_, q)| map(q))
     }
 
     pub fn transfer<R>(
         &mut self,
-        container: Option<&Children>,
+        container: Entity,
         from: &Handle<ItemAsset>,
         to: &Handle<ItemAsset>,
         map: impl FnOnce(Q::Item<'_>, Q::Item<'_>) -> R,
     ) -> Option<R> {
-        let mut from_entity = None;
-        let mut to_entity = None;
+        let query = self.query.to_readonly();
+        let from = find(&self.index, &query, from, container)?;
+        let to = find(&self.index, &query, to, container)?;
 
-        for &entity in container? {
-            if let Ok((asset, _)) = self.query.get_mut(entity) {
-                if asset == from {
-                    from_entity = Some(entity);
-                }
-                if asset == to {
-                    to_entity = Some(entity);
-                }
+        let [(_, from), (_, to)] = self.query.get_many_mut([from, to]).ok()?;
+        Some(map(from, to))
+    }
+
+    /// First item passing the filter.
+    pub fn find<R>(
+        &mut self,
+        filter: ItemFilter,
+        container: Entity,
+        map: impl FnOnce(Q::Item<'_>) -> R,
+    ) -> Option<R> {
+        let entity = self.to_readonly().entities(filter, container).next()?;
+        self.query.get_mut(entity).ok().map(|(_, q)| map(q))
+    }
+
+    /// Calls `f` for every item passing the filter.
+    pub fn for_each(
+        &mut self,
+        filter: ItemFilter,
+        container: Entity,
+        mut f: impl FnMut(&Handle<ItemAsset>, Q::Item<'_>),
+    ) {
+        let entities: Vec<_> = self.to_readonly().entities(filter, container).collect();
+        for entity in entities {
+            if let Ok((handle, q)) = self.query.get_mut(entity) {
+                f(handle, q);
             }
         }
+    }
 
-        from_entity.zip(to_entity).map(|(from, to)| {
-            let [(_, from), (_, to)] = self.query.get_many_mut([from, to]).unwrap();
-            map(from, to)
-        })
+    /// First item passing the filter, also inside nested containers.
+    pub fn find_recursive<R>(
+        &mut self,
+        filter: ItemFilter,
+        container: Entity,
+        map: impl FnOnce(Q::Item<'_>) -> R,
+    ) -> Option<R> {
+        let entity = self
+            .to_readonly()
+            .entities_recursive(filter, container)
+            .next()?;
+        self.query.get_mut(entity).ok().map(|(_, q)| map(q))
+    }
+
+    /// Calls `f` for every item passing the filter, also inside nested containers.
+    pub fn for_each_recursive(
+        &mut self,
+        filter: ItemFilter,
+        container: Entity,
+        mut f: impl FnMut(&Handle<ItemAsset>, Q::Item<'_>),
+    ) {
+        let entities: Vec<_> = self
+            .to_readonly()
+            .entities_recursive(filter, container)
+            .collect();
+        for entity in entities {
+            if let Ok((handle, q)) = self.query.get_mut(entity) {
+                f(handle, q);
+            }
+        }
+    }
+}
+
+/// First indexed item which is still the asset, the index lags behind moves made this frame.
+fn find<Q: ReadOnlyWorldQuery>(
+    index: &Query<Read<ContainerIndex>>,
+    query: &Query<(Read<Handle<ItemAsset>>, Q)>,
+    item: &Handle<ItemAsset>,
+    container: Entity,
+) -> Option<Entity> {
+    index
+        .get(container)
+        .ok()?
+        .items(item)
+        .find(|&entity| query.get(entity).is_ok_and(|(asset, _)| asset == item))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mechanics::item::ItemWeight;
+
+    const ITEM: Handle<ItemAsset> = Handle::weak_from_u128(1);
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.init_resource::<Assets<ItemAsset>>()
+            .add_event::<PutInContainer>()
+            .add_event::<ContainerRejected>()
+            .add_systems(Update, put_in_container);
+        app
+    }
+
+    fn container(app: &mut App, weight: f32) -> Entity {
+        let capacity = ContainerCapacity {
+            weight,
+            ..ContainerCapacity::UNLIMITED
+        };
+        app.world
+            .spawn(ContainerBundle {
+                capacity,
+                ..default()
+            })
+            .id()
+    }
+
+    fn stack(app: &mut App, current: f32, parent: Entity) -> Entity {
+        let maximum = 10.0;
+        let stack = (ITEM, Consumable { current, maximum }, ItemWeight(1.0));
+        let stack = app.world.spawn(stack).id();
+        app.world.entity_mut(parent).add_child(stack);
+        stack
+    }
+
+    fn units(app: &App, container: Entity) -> Vec<f32> {
+        let children = app.world.get::<Children>(container).into_iter().flatten();
+        children
+            .filter_map(|&entity| app.world.get::<Consumable>(entity))
+            .map(|stack| stack.current)
+            .collect()
+    }
+
+    #[test]
+    fn admit_splits_a_stack_that_fits_partially() {
+        let mut app = app();
+        let from = container(&mut app, f32::INFINITY);
+        let to = container(&mut app, 4.0);
+        let item = stack(&mut app, 10.0, from);
+
+        app.world.send_event(PutInContainer { item, to });
+        app.update();
+
+        assert_eq!(units(&app, to), [4.0]);
+        // the rest is not lost, it stays where the stack was
+        assert_eq!(units(&app, from), [6.0]);
+
+        let rejected = app.world.resource::<Events<ContainerRejected>>();
+        let rejected: Vec<_> = rejected.iter_current_update_events().collect();
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].amount, 6.0);
+        assert_eq!(rejected[0].reason, Overfill::Weight);
+    }
+
+    #[test]
+    fn admit_rejects_what_does_not_fit_at_all() {
+        let mut app = app();
+        let from = container(&mut app, f32::INFINITY);
+        let to = container(&mut app, 0.0);
+        let item = stack(&mut app, 3.0, from);
+
+        app.world.send_event(PutInContainer { item, to });
+        app.update();
+
+        assert!(units(&app, to).is_empty());
+        assert_eq!(units(&app, from), [3.0]);
+    }
+
+    #[test]
+    fn admit_respects_the_containers_around_the_target() {
+        let mut app = app();
+        let from = container(&mut app, f32::INFINITY);
+        let inventory = container(&mut app, 5.0);
+        let bag = container(&mut app, f32::INFINITY);
+        app.world.entity_mut(bag).insert(ITEM);
+        app.world.entity_mut(inventory).add_child(bag);
+        let item = stack(&mut app, 10.0, from);
+
+        app.world.send_event(PutInContainer { item, to: bag });
+        app.update();
+
+        // the bag holds any weight, but the inventory carrying it does not
+        assert_eq!(units(&app, bag), [5.0]);
+        assert_eq!(units(&app, from), [5.0]);
     }
 }
@@ -0,0 +1,114 @@
+use super::{ItemAsset, ReflectReloadItem, ReloadItem};
+use bevy::prelude::*;
+
+/// Item which goes off over time.
+///
+/// Units joining a stack blend in with the freshness they had, weighted by amount.
+/// Freshness is kept on reload, the rate and what it spoils into come from the item file.
+#[derive(Component, Clone, Debug, Reflect)]
+#[reflect(Component, ReloadItem)]
+pub struct Perishable {
+    /// Freshness lost per second.
+    pub rate: f32,
+    /// From `1.0` when fresh down to `0.0` when spoiled.
+    pub freshness: f32,
+    /// Path of the item the spoiled one turns into, it is destroyed when `None`.
+    pub spoils_into: Option<String>,
+}
+
+impl Perishable {
+    /// Freshness of `units` of `freshness` put together with `added` units of `other`.
+    pub fn mix(freshness: f32, units: f32, other: f32, added: f32) -> f32 {
+        let (units, added) = (units.max(0.0), added.max(0.0));
+        if units + added <= 0.0 {
+            return freshness;
+        }
+        (freshness * units + other * added) / (units + added)
+    }
+
+    /// Blends in `added` units of `freshness` joining a stack of `units`.
+    pub fn blend(&mut self, units: f32, added: f32, freshness: f32) {
+        self.freshness = Self::mix(self.freshness, units, freshness, added);
+    }
+}
+
+impl Default for Perishable {
+    fn default() -> Self {
+        Self {
+            rate: 0.0,
+            freshness: 1.0,
+            spoils_into: None,
+        }
+    }
+}
+
+impl ReloadItem for Perishable {
+    fn reload(&mut self, template: &Self) {
+        self.rate = template.rate;
+        self.spoils_into = template.spoils_into.clone();
+    }
+}
+
+/// Item which wears out over time and breaks.
+#[derive(Component, Clone, Debug, Reflect)]
+#[reflect(Component, ReloadItem)]
+pub struct Durability {
+    pub current: f32,
+    pub maximum: f32,
+    /// Durability lost per second.
+    pub wear: f32,
+}
+
+impl Default for Durability {
+    fn default() -> Self {
+        Self {
+            current: 100.0,
+            maximum: 100.0,
+            wear: 0.0,
+        }
+    }
+}
+
+impl ReloadItem for Durability {
+    fn reload(&mut self, template: &Self) {
+        self.maximum = template.maximum;
+        self.wear = template.wear;
+        self.current = self.current.min(self.maximum);
+    }
+}
+
+pub fn decay_items(
+    time: Res<Time<Virtual>>,
+    mut commands: Commands,
+    server: Res<AssetServer>,
+    mut perishables: Query<(Entity, &mut Perishable)>,
+    mut durables: Query<(Entity, &mut Durability)>,
+) {
+    let delta = time.delta_seconds();
+
+    for (entity, mut perishable) in &mut perishables {
+        let before = perishable.freshness;
+        perishable.freshness = (before - perishable.rate * delta).max(0.0);
+
+        if before > 0.0 && perishable.freshness <= 0.0 {
+            debug!("{:?} spoiled", entity);
+
+            // a new handle turns it into the other item, keeping the amount
+            if let Some(path) = &perishable.spoils_into {
+                let spoiled: Handle<ItemAsset> = server.load(path);
+                commands.entity(entity).insert(spoiled);
+            } else {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+
+    for (entity, mut durability) in &mut durables {
+        durability.current = (durability.current - durability.wear * delta).max(0.0);
+
+        if durability.current <= 0.0 {
+            debug!("{:?} broke", entity);
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
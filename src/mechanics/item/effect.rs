@@ -0,0 +1,31 @@
+use bevy::prelude::*;
+
+/// Lowers the user's [`Fatigue`](crate::mechanics::Fatigue) by this much per use.
+#[derive(Component, Clone, Copy, Debug, Default, Reflect)]
+#[reflect(Component)]
+pub struct RestoreFatigue(pub f32);
+
+/// Lowers the user's [`Hunger`](crate::mechanics::Hunger) by this much per use.
+#[derive(Component, Clone, Copy, Debug, Default, Reflect)]
+#[reflect(Component)]
+pub struct RestoreHunger(pub f32);
+
+/// Multiplies the user's [`CharacterController::speed`](crate::character::CharacterController)
+/// for a while after each use.
+#[derive(Component, Clone, Copy, Debug, Reflect)]
+#[reflect(Component)]
+pub struct SpeedBuff {
+    /// Has to be above zero, a buff with any other value is not applied.
+    pub multiplier: f32,
+    /// Seconds the buff lasts.
+    pub duration: f32,
+}
+
+impl Default for SpeedBuff {
+    fn default() -> Self {
+        Self {
+            multiplier: 1.0,
+            duration: 0.0,
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use super::{Consumable, ItemAdded, ItemAsset, ItemQuantityChanged, ItemRemoved};
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+/// Items of a [`Container`](super::Container) grouped by their asset.
+///
+/// Updated from the container change events at the end of every frame.
+#[derive(Component, Default, Debug)]
+pub struct ContainerIndex {
+    entries: HashMap<AssetId<ItemAsset>, IndexEntry>,
+}
+
+#[derive(Default, Debug)]
+struct IndexEntry {
+    items: Vec<(Entity, f32)>,
+    total: f32,
+}
+
+impl ContainerIndex {
+    /// Item entities of the asset, in the order they were put in.
+    pub fn items(
+        &self,
+        item: impl Into<AssetId<ItemAsset>>,
+    ) -> impl DoubleEndedIterator<Item = Entity> + '_ {
+        self.entries
+            .get(&item.into())
+            .into_iter()
+            .flat_map(|entry| entry.items.iter().map(|&(entity, _)| entity))
+    }
+
+    /// Units of the asset, items which are not [`Consumable`] count as `1.0`.
+    pub fn total(&self, item: impl Into<AssetId<ItemAsset>>) -> f32 {
+        self.entries
+            .get(&item.into())
+            .map_or(0.0, |entry| entry.total)
+    }
+
+    pub fn assets(&self) -> impl Iterator<Item = AssetId<ItemAsset>> + '_ {
+        self.entries.keys().copied()
+    }
+
+    fn insert(&mut self, asset: AssetId<ItemAsset>, item: Entity, units: f32) {
+        let entry = self.entries.entry(asset).or_default();
+        entry.items.push((item, units));
+        entry.total += units;
+    }
+
+    fn remove(&mut self, asset: AssetId<ItemAsset>, item: Entity) {
+        let Some(entry) = self.entries.get_mut(&asset) else {
+            return;
+        };
+
+        if let Some(position) = entry.items.iter().position(|&(e, _)| e == item) {
+            let (_, units) = entry.items.remove(position);
+            entry.total -= units;
+        }
+
+        if entry.items.is_empty() {
+            self.entries.remove(&asset);
+        }
+    }
+
+    fn update(&mut self, asset: AssetId<ItemAsset>, item: Entity, units: f32) {
+        let Some(entry) = self.entries.get_mut(&asset) else {
+            return;
+        };
+
+        if let Some((_, previous)) = entry.items.iter_mut().find(|(e, _)| *e == item) {
+            entry.total += units - *previous;
+            *previous = units;
+        }
+    }
+}
+
+pub fn update_container_index(
+    mut added: EventReader<ItemAdded>,
+    mut removed: EventReader<ItemRemoved>,
+    mut changed: EventReader<ItemQuantityChanged>,
+    consumables: Query<&Consumable>,
+    mut indexes: Query<&mut ContainerIndex>,
+) {
+    for event in removed.read() {
+        if let Ok(mut index) = indexes.get_mut(event.container) {
+            index.remove(event.asset.id(), event.item);
+        }
+    }
+
+    for event in added.read() {
+        if let Ok(mut index) = indexes.get_mut(event.container) {
+            let units = consumables.get(event.item).map_or(1.0, |c| c.current);
+            index.insert(event.asset.id(), event.item, units);
+        }
+    }
+
+    for event in changed.read() {
+        if let Ok(mut index) = indexes.get_mut(event.container) {
+            index.update(event.asset.id(), event.item, event.current);
+        }
+    }
+}
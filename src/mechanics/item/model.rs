@@ -0,0 +1,129 @@
+use super::{Item, ItemAsset};
+use crate::loading::AssetCache;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+/// How an item looks when it lies in the world.
+#[derive(Component, Clone, Copy, Debug, Reflect)]
+#[reflect(Component)]
+pub struct ItemModel {
+    pub shape: ItemShape,
+    pub color: Color,
+}
+
+impl Default for ItemModel {
+    fn default() -> Self {
+        Self {
+            shape: ItemShape::default(),
+            color: Color::GRAY,
+        }
+    }
+}
+
+/// glTF scene shown instead of the [`ItemModel`] shape, e.g. `models/bag.glb#Scene0`.
+///
+/// Loaded together with the item, see [`ItemAsset::scene`].
+#[derive(Component, Clone, Debug, Default, Reflect)]
+#[reflect(Component)]
+pub struct ItemScene(pub String);
+
+/// Image shown for the item in the HUD, e.g. `icons/potion.png`.
+///
+/// Loaded together with the item, see [`ItemAsset::icon`].
+#[derive(Component, Clone, Debug, Default, Reflect)]
+#[reflect(Component)]
+pub struct ItemIcon(pub String);
+
+#[derive(Clone, Copy, Debug, PartialEq, Reflect)]
+pub enum ItemShape {
+    Cube { size: f32 },
+    Sphere { radius: f32 },
+}
+
+impl Default for ItemShape {
+    fn default() -> Self {
+        Self::Cube { size: 0.3 }
+    }
+}
+
+impl std::cmp::Eq for ItemShape {}
+
+impl std::hash::Hash for ItemShape {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match *self {
+            Self::Cube { size } => size.to_bits().hash(state),
+            Self::Sphere { radius } => radius.to_bits().hash(state),
+        }
+    }
+}
+
+impl From<ItemShape> for Mesh {
+    fn from(shape: ItemShape) -> Self {
+        match shape {
+            ItemShape::Cube { size } => shape::Cube::new(size).into(),
+            ItemShape::Sphere { radius } => shape::UVSphere {
+                radius,
+                sectors: 12,
+                stacks: 8,
+            }
+            .into(),
+        }
+    }
+}
+
+#[derive(SystemParam)]
+pub struct ItemModels<'w> {
+    cache: ResMut<'w, AssetCache>,
+    meshes: ResMut<'w, Assets<Mesh>>,
+    materials: ResMut<'w, Assets<StandardMaterial>>,
+}
+
+impl ItemModels<'_> {
+    pub fn mesh(&mut self, model: ItemModel) -> (Handle<Mesh>, Handle<StandardMaterial>) {
+        let mesh = self.cache.get_item_mesh(&mut self.meshes, model.shape);
+        let material = self.cache.get_material(&mut self.materials, model.color);
+        (mesh, material)
+    }
+}
+
+pub type ItemVisualsQuery<'a> = (
+    Entity,
+    &'a Handle<ItemAsset>,
+    Option<&'a ItemModel>,
+    Has<Transform>,
+);
+
+/// Spawned, converted or reloaded items.
+pub type ItemVisualsChanged = (
+    With<Item>,
+    Or<(Added<Item>, Changed<Handle<ItemAsset>>, Changed<ItemModel>)>,
+);
+
+/// Gives items their scene, or a mesh made from their [`ItemModel`].
+///
+/// Items stay hidden while inside a [`Container`](super::Container).
+pub fn attach_item_visuals(
+    mut commands: Commands,
+    items: Query<ItemVisualsQuery, ItemVisualsChanged>,
+    assets: Res<Assets<ItemAsset>>,
+    mut models: ItemModels,
+) {
+    for (entity, handle, model, has_transform) in &items {
+        let mut entity = commands.entity(entity);
+
+        // keep where a dropped item lies
+        if !has_transform {
+            entity.insert(SpatialBundle::INHERITED_IDENTITY);
+        }
+
+        if let Some(scene) = assets.get(handle).and_then(|asset| asset.scene.clone()) {
+            entity
+                .remove::<(Handle<Mesh>, Handle<StandardMaterial>)>()
+                .insert(scene);
+        } else {
+            let (mesh, material) = models.mesh(model.copied().unwrap_or_default());
+            entity.remove::<Handle<Scene>>().insert((mesh, material));
+        }
+    }
+}
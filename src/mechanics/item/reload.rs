@@ -0,0 +1,184 @@
+use super::spawn::{item_component, item_registration, ItemComponents};
+use super::{Item, ItemAsset, ItemSpawnError};
+use bevy::asset::LoadState;
+use bevy::ecs::event::ManualEventReader;
+use bevy::prelude::*;
+use bevy::reflect::{FromType, TypeRegistry};
+use bevy::utils::HashSet;
+
+/// Enables re-applying modified [`ItemAsset`]s onto already spawned items.
+#[derive(Resource, Default)]
+pub struct ItemHotReload;
+
+/// Component with per-instance state that must survive an item reload.
+pub trait ReloadItem: Component {
+    /// Takes the new template values, keeping what belongs to this instance.
+    fn reload(&mut self, template: &Self);
+}
+
+/// Type data for components implementing [`ReloadItem`].
+#[derive(Clone)]
+pub struct ReflectReloadItem {
+    reload: fn(&mut EntityWorldMut, &dyn Reflect),
+}
+
+impl ReflectReloadItem {
+    pub fn reload(&self, entity: &mut EntityWorldMut, template: &dyn Reflect) {
+        (self.reload)(entity, template);
+    }
+}
+
+impl<T: ReloadItem + FromReflect> FromType<T> for ReflectReloadItem {
+    fn from_type() -> Self {
+        Self {
+            reload: |entity, template| {
+                let Some(template) = T::from_reflect(template) else {
+                    return;
+                };
+                if let Some(mut component) = entity.get_mut::<T>() {
+                    component.reload(&template);
+                }
+            },
+        }
+    }
+}
+
+pub type ReloadQuery<'a> = Option<QueryState<(Entity, &'a Handle<ItemAsset>), With<Item>>>;
+
+pub fn reload_items_system(
+    world: &mut World,
+    mut reader: Local<ManualEventReader<AssetEvent<ItemAsset>>>,
+    mut query: Local<ReloadQuery>,
+    mut entities: Local<Vec<(Entity, AssetId<ItemAsset>)>>,
+) {
+    let events = world.resource::<Events<AssetEvent<ItemAsset>>>();
+    let modified: HashSet<_> = reader
+        .read(events)
+        .filter_map(|event| match *event {
+            AssetEvent::Modified { id } => Some(id),
+            _ => None,
+        })
+        .collect();
+
+    if modified.is_empty() {
+        return;
+    }
+
+    let query = query
+        .get_or_insert_with(|| world.query_filtered::<(Entity, &Handle<ItemAsset>), With<Item>>());
+
+    query.update_archetypes(world);
+
+    entities.extend(
+        query
+            .iter(world)
+            .map(|(e, h)| (e, h.id()))
+            .filter(|(_, id)| modified.contains(id)),
+    );
+
+    let registry = world.resource::<AppTypeRegistry>().clone();
+    let registry = registry.read();
+
+    world.resource_scope(|world, assets: Mut<Assets<ItemAsset>>| {
+        for (entity, id) in entities.drain(..) {
+            if let Err(err) = reload_item(&registry, &assets, id, world.entity_mut(entity)) {
+                error!("reloading item err: {:?}", err);
+            }
+
+            debug!("reload item for {:?}", entity);
+        }
+    });
+}
+
+pub type ConvertQuery<'a> =
+    Option<QueryState<(Entity, &'a Handle<ItemAsset>), (With<Item>, Changed<Handle<ItemAsset>>)>>;
+
+/// Applies the new template onto spawned items whose [`ItemAsset`] handle was replaced,
+/// turning them into another item the same way a reload does.
+pub fn convert_items_system(
+    world: &mut World,
+    mut query: Local<ConvertQuery>,
+    mut pending: Local<Vec<(Entity, AssetId<ItemAsset>)>>,
+) {
+    let query = query.get_or_insert_with(|| {
+        world.query_filtered::<(Entity, &Handle<ItemAsset>), (With<Item>, Changed<Handle<ItemAsset>>)>()
+    });
+
+    pending.extend(query.iter(world).map(|(e, h)| (e, h.id())));
+
+    if pending.is_empty() {
+        return;
+    }
+
+    let registry = world.resource::<AppTypeRegistry>().clone();
+    let registry = registry.read();
+
+    world.resource_scope(|world, assets: Mut<Assets<ItemAsset>>| {
+        pending.retain(|&(entity, id)| {
+            // gone, or replaced once more in the meantime
+            if world.get::<Handle<ItemAsset>>(entity).map(Handle::id) != Some(id) {
+                return false;
+            }
+
+            // wait for the new item to load
+            let failed =
+                world.resource::<AssetServer>().get_load_state(id) == Some(LoadState::Failed);
+            if !assets.contains(id) && !failed {
+                return true;
+            }
+
+            if let Err(err) = reload_item(&registry, &assets, id, world.entity_mut(entity)) {
+                error!("converting item err: {:?}", err);
+            }
+
+            debug!("convert item for {:?}", entity);
+            false
+        });
+    });
+}
+
+fn reload_item(
+    registry: &TypeRegistry,
+    assets: &Assets<ItemAsset>,
+    id: AssetId<ItemAsset>,
+    mut entity: EntityWorldMut<'_>,
+) -> Result<(), ItemSpawnError> {
+    let err = ItemSpawnError::NonExistentItem { id };
+    let asset = assets.get(id).ok_or(err)?;
+
+    let previous = entity.take::<ItemComponents>().unwrap_or_default();
+    let mut applied = ItemComponents::default();
+
+    for reflect in &asset.components {
+        let registration = item_registration(registry, &**reflect)?;
+        let component = item_component(registration)?;
+
+        if !component.contains(EntityRef::from(&entity)) {
+            component.insert(&mut entity, &**reflect);
+        } else if let Some(reload) = registration.data::<ReflectReloadItem>() {
+            reload.reload(&mut entity, &**reflect);
+        } else {
+            component.apply(&mut entity, &**reflect);
+        }
+
+        applied.types.push(registration.type_id());
+    }
+
+    // drop what was removed from the item file
+    for type_id in previous.types {
+        if applied.types.contains(&type_id) {
+            continue;
+        }
+
+        let component = registry
+            .get(type_id)
+            .and_then(|r| r.data::<ReflectComponent>());
+        if let Some(component) = component {
+            component.remove(&mut entity);
+        }
+    }
+
+    entity.insert(applied);
+
+    Ok(())
+}
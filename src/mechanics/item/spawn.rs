@@ -1,10 +1,58 @@
-use super::{Item, ItemAsset};
+use super::{Container, Item, ItemAsset, Loads, Overfill, ReadCapacity};
+use bevy::asset::LoadState;
+use bevy::ecs::system::SystemState;
 use bevy::prelude::*;
-use bevy::reflect::TypeRegistry;
+use bevy::reflect::{TypeRegistration, TypeRegistry};
 use bevy::utils::thiserror::{self, Error};
+use std::any::TypeId;
+
+/// Component types an item received from its [`ItemAsset`].
+#[derive(Component, Default, Debug)]
+pub struct ItemComponents {
+    pub types: Vec<TypeId>,
+}
+
+/// Spawns an item into a container, with some of its template components replaced.
+///
+/// Overrides are applied on top of the template values, so a dynamic struct may
+/// change a single field. Every override must be a component the template has.
+#[derive(Event, Debug)]
+pub struct ItemSpawnRequest {
+    pub requester: Entity,
+    pub item: Handle<ItemAsset>,
+    pub container: Entity,
+    pub overrides: Vec<Box<dyn Reflect>>,
+}
+
+impl ItemSpawnRequest {
+    pub fn new(requester: Entity, item: &Handle<ItemAsset>, container: Entity) -> Self {
+        Self {
+            requester,
+            item: item.clone(),
+            container,
+            overrides: Vec::new(),
+        }
+    }
+
+    pub fn with_override(mut self, component: impl Reflect) -> Self {
+        self.overrides.push(Box::new(component));
+        self
+    }
+}
+
+/// The item spawned for an [`ItemSpawnRequest`], nothing is spawned on error.
+#[derive(Event, Debug)]
+pub struct ItemSpawnResult {
+    pub requester: Entity,
+    pub result: Result<Entity, ItemSpawnError>,
+}
 
 pub type ItemQuery<'a> = Option<QueryState<(Entity, &'a Handle<ItemAsset>), Without<Item>>>;
 
+/// Applies the template onto entities which were given a bare item handle.
+///
+/// Components the entity already has are kept, so `(handle, Consumable { .. })`
+/// spawns a stack of the chosen amount. [`ItemSpawnRequest`] checks such overrides.
 pub fn spawn_items_system(
     world: &mut World,
     mut query: Local<ItemQuery>,
@@ -29,7 +77,7 @@ pub fn spawn_items_system(
 
             let entity_id = entity.id();
 
-            if let Err(err) = spawn_item(&registry, &assets, id, entity) {
+            if let Err(err) = spawn_item(&registry, &assets, id, entity, &[]) {
                 error!("spawning entities err: {:?}", err);
             }
 
@@ -38,45 +86,184 @@ pub fn spawn_items_system(
     });
 }
 
+pub type CapacityState = Option<SystemState<ReadCapacity<'static, 'static>>>;
+
+pub fn spawn_requested_items(
+    world: &mut World,
+    mut pending: Local<Vec<ItemSpawnRequest>>,
+    mut capacity: Local<CapacityState>,
+    mut spawned: Local<Vec<(ItemSpawnRequest, Result<Entity, ItemSpawnError>)>>,
+) {
+    pending.extend(world.resource_mut::<Events<ItemSpawnRequest>>().drain());
+
+    if pending.is_empty() {
+        return;
+    }
+
+    let registry = world.resource::<AppTypeRegistry>().clone();
+    let registry = registry.read();
+
+    world.resource_scope(|world, assets: Mut<Assets<ItemAsset>>| {
+        for request in std::mem::take(&mut *pending) {
+            // wait for the item to load, a handle which is not loading is answered right away
+            let id = request.item.id();
+            let state = world.resource::<AssetServer>().get_load_state(id);
+            let loading = matches!(state, Some(LoadState::NotLoaded | LoadState::Loading));
+            if !assets.contains(id) && loading {
+                pending.push(request);
+                continue;
+            }
+
+            let result = if state == Some(LoadState::Failed) {
+                Err(ItemSpawnError::LoadFailed { id })
+            } else {
+                spawn_requested(&registry, &assets, world, &request)
+            };
+            spawned.push((request, result));
+        }
+    });
+
+    let capacity = capacity.get_or_insert_with(|| SystemState::new(world));
+
+    for (request, mut result) in spawned.drain(..) {
+        if let Ok(item) = result {
+            // checked like an admission, the containers around it have to carry it too
+            let fit = {
+                let capacity = capacity.get(world);
+                let size = capacity.size(item);
+                let containers = capacity.enclosing(request.container, item);
+                let mut loads = Loads::default();
+                loads.fit(&capacity, &containers, &size, true).1
+            };
+
+            if let Some(reason) = fit {
+                world.entity_mut(item).despawn_recursive();
+                result = Err(ItemSpawnError::NoRoom {
+                    container: request.container,
+                    reason,
+                });
+            } else {
+                world.entity_mut(request.container).add_child(item);
+            }
+        }
+
+        if let Err(err) = &result {
+            debug!("item spawn for {:?} failed: {}", request.requester, err);
+        }
+
+        world.send_event(ItemSpawnResult {
+            requester: request.requester,
+            result,
+        });
+    }
+}
+
+fn spawn_requested(
+    registry: &TypeRegistry,
+    assets: &Assets<ItemAsset>,
+    world: &mut World,
+    request: &ItemSpawnRequest,
+) -> Result<Entity, ItemSpawnError> {
+    let id = request.item.id();
+    let asset = assets
+        .get(id)
+        .ok_or(ItemSpawnError::NonExistentItem { id })?;
+
+    if world.get::<Container>(request.container).is_none() {
+        return Err(ItemSpawnError::NotAContainer {
+            entity: request.container,
+        });
+    }
+
+    // check everything before spawning, so a failed request leaves nothing behind
+    let template: Vec<TypeId> = asset
+        .components
+        .iter()
+        .map(|reflect| item_registration(registry, &**reflect).map(TypeRegistration::type_id))
+        .collect::<Result<_, _>>()?;
+
+    for reflect in &request.overrides {
+        let registration = item_registration(registry, &**reflect)?;
+        item_component(registration)?;
+
+        if !template.contains(&registration.type_id()) {
+            return Err(ItemSpawnError::NotInTemplate {
+                type_path: registration.type_info().type_path().to_string(),
+            });
+        }
+    }
+
+    let entity = world.spawn((request.item.clone(), Item));
+    let item = entity.id();
+    spawn_item(registry, assets, id, entity, &request.overrides)?;
+
+    Ok(item)
+}
+
 fn spawn_item(
     registry: &TypeRegistry,
     assets: &Assets<ItemAsset>,
     id: AssetId<ItemAsset>,
     mut entity: EntityWorldMut<'_>,
+    overrides: &[Box<dyn Reflect>],
 ) -> Result<(), ItemSpawnError> {
     let err = ItemSpawnError::NonExistentItem { id };
     let asset = assets.get(id).ok_or(err)?;
 
-    for reflect in &asset.components {
-        let type_info = reflect.get_represented_type_info().ok_or_else(|| {
-            ItemSpawnError::NoRepresentedType {
-                type_path: reflect.reflect_type_path().to_string(),
-            }
-        })?;
-
-        let registration = registry.get(type_info.type_id()).ok_or_else(|| {
-            ItemSpawnError::UnregisteredButReflectedType {
-                type_path: type_info.type_path().to_string(),
-            }
-        })?;
+    let mut applied = ItemComponents::default();
 
-        let component = registration.data::<ReflectComponent>().ok_or_else(|| {
-            ItemSpawnError::UnregisteredComponent {
-                type_path: type_info.type_path().to_string(),
-            }
-        })?;
+    for reflect in &asset.components {
+        let registration = item_registration(registry, &**reflect)?;
+        let component = item_component(registration)?;
 
         // do not overwrite
         if !component.contains(EntityRef::from(&entity)) {
             component.insert(&mut entity, &**reflect);
         }
+
+        applied.types.push(registration.type_id());
     }
 
+    for reflect in overrides {
+        let registration = item_registration(registry, &**reflect)?;
+        item_component(registration)?.apply(&mut entity, &**reflect);
+    }
+
+    entity.insert(applied);
+
     Ok(())
 }
 
+pub fn item_registration<'a>(
+    registry: &'a TypeRegistry,
+    reflect: &dyn Reflect,
+) -> Result<&'a TypeRegistration, ItemSpawnError> {
+    let type_info =
+        reflect
+            .get_represented_type_info()
+            .ok_or_else(|| ItemSpawnError::NoRepresentedType {
+                type_path: reflect.reflect_type_path().to_string(),
+            })?;
+
+    registry
+        .get(type_info.type_id())
+        .ok_or_else(|| ItemSpawnError::UnregisteredButReflectedType {
+            type_path: type_info.type_path().to_string(),
+        })
+}
+
+pub fn item_component(
+    registration: &TypeRegistration,
+) -> Result<&ReflectComponent, ItemSpawnError> {
+    registration
+        .data::<ReflectComponent>()
+        .ok_or_else(|| ItemSpawnError::UnregisteredComponent {
+            type_path: registration.type_info().type_path().to_string(),
+        })
+}
+
 /// Errors that can occur when spawning a item.
-#[derive(Error, Debug)]
+#[derive(Error, Clone, Debug)]
 pub enum ItemSpawnError {
     /// Item contains a proxy without a represented type.
     #[error("item contains dynamic type `{type_path}` without a represented type. consider changing this using `set_represented_type`.")]
@@ -107,4 +294,34 @@ pub enum ItemSpawnError {
         /// Id of the non-existent item.
         id: AssetId<ItemAsset>,
     },
+
+    /// The item file could not be loaded.
+    #[error("item failed to load")]
+    LoadFailed {
+        /// Id of the item.
+        id: AssetId<ItemAsset>,
+    },
+
+    /// Override of a component the item template does not have.
+    #[error("item template has no component `{type_path}` to override")]
+    NotInTemplate {
+        /// Type of the override.
+        type_path: String,
+    },
+
+    /// Target of the spawn is not a container.
+    #[error("entity {entity:?} is not a container")]
+    NotAContainer {
+        /// The entity which was given as container.
+        entity: Entity,
+    },
+
+    /// The item does not fit in the container.
+    #[error("container {container:?} has no room ({reason:?}) for the item")]
+    NoRoom {
+        /// The full container.
+        container: Entity,
+        /// Which limit the item would exceed.
+        reason: Overfill,
+    },
 }
@@ -0,0 +1,528 @@
+use super::{Admission, Consumable, ItemAsset, Perishable};
+use bevy::prelude::*;
+
+/// Moves `amount` units of the `item` stack into a new stack put in `to`.
+///
+/// Units which do not fit in `to` stay in the stack and are reported as
+/// [`ContainerRejected`](super::ContainerRejected).
+#[derive(Event, Clone, Copy, Debug)]
+pub struct SplitStack {
+    /// Who asked for the split, echoed in [`StackResult`].
+    pub requester: Entity,
+    pub item: Entity,
+    pub amount: f32,
+    pub to: Entity,
+}
+
+/// Moves as much of the `from` stack as fits into the `to` stack of the same item.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct MergeStacks {
+    /// Who asked for the merge, echoed in [`StackResult`].
+    pub requester: Entity,
+    pub from: Entity,
+    pub to: Entity,
+}
+
+/// Moves `amount` units of `item` from the `from` container into the `to` container,
+/// topping up a stack already there or starting a new one.
+///
+/// Units which do not fit in `to` stay in `from` and are reported as
+/// [`ContainerRejected`](super::ContainerRejected).
+#[derive(Event, Clone, Debug)]
+pub struct TransferStack {
+    /// Who asked for the transfer, echoed in [`StackResult`].
+    pub requester: Entity,
+    pub item: Handle<ItemAsset>,
+    pub amount: f32,
+    pub from: Entity,
+    pub to: Entity,
+}
+
+/// Outcome of [`SplitStack`], [`MergeStacks`] and [`TransferStack`].
+#[derive(Event, Clone, Copy, Debug)]
+pub struct StackResult {
+    pub requester: Entity,
+    /// Stack the units were taken from, the first one when there were several.
+    pub source: Option<Entity>,
+    /// Stack the units were put in, the first one when there were several.
+    pub target: Option<Entity>,
+    pub moved: f32,
+    /// Requested units that were not moved.
+    pub overflow: f32,
+}
+
+pub type StackQuery<'a> = (
+    &'a Handle<ItemAsset>,
+    &'a mut Consumable,
+    Option<&'a mut Perishable>,
+);
+
+pub fn split_stack(
+    mut commands: Commands,
+    mut events: ResMut<Events<SplitStack>>,
+    mut results: EventWriter<StackResult>,
+    mut set: ParamSet<(Admission, Query<StackQuery>)>,
+) {
+    set.p0().clear();
+    for SplitStack {
+        requester,
+        item,
+        amount,
+        to,
+    } in events.drain()
+    {
+        let Ok(current) = set.p1().get(item).map(|(_, stack, _)| stack.current) else {
+            warn!("split of {:?} which is not a stack", item);
+            results.send(StackResult {
+                requester,
+                source: None,
+                target: None,
+                moved: 0.0,
+                overflow: amount.max(0.0),
+            });
+            continue;
+        };
+
+        let available = amount.clamp(0.0, current.max(0.0));
+        if available <= 0.0 {
+            warn!(
+                "split of {} units of {:?} leaves nothing to move",
+                amount, item
+            );
+            results.send(StackResult {
+                requester,
+                source: Some(item),
+                target: None,
+                moved: 0.0,
+                overflow: amount.max(0.0),
+            });
+            continue;
+        }
+
+        // what does not fit stays in the stack
+        let mut admission = set.p0();
+        let (fit, reason) = admission.reserve(item, available, to, true);
+        if let Some(reason) = reason {
+            admission.reject(item, to, available - fit, reason);
+        }
+
+        let mut stacks = set.p1();
+        let (asset, mut stack, perishable) = stacks.get_mut(item).unwrap();
+        let moved = stack.take_amount(fit);
+        if moved <= 0.0 {
+            results.send(StackResult {
+                requester,
+                source: Some(item),
+                target: None,
+                moved: 0.0,
+                overflow: amount.max(0.0),
+            });
+            continue;
+        }
+        let split = Consumable {
+            current: moved,
+            maximum: stack.maximum,
+        };
+
+        // spawned with its own amount, the template does not overwrite it
+        let mut target = commands.spawn((asset.clone(), split));
+        if let Some(perishable) = perishable {
+            target.insert(perishable.clone());
+        }
+        let target = target.id();
+        commands.entity(to).add_child(target);
+
+        results.send(StackResult {
+            requester,
+            source: Some(item),
+            target: Some(target),
+            moved,
+            overflow: amount - moved,
+        });
+    }
+}
+
+pub fn merge_stacks(
+    mut events: ResMut<Events<MergeStacks>>,
+    mut results: EventWriter<StackResult>,
+    mut stacks: Query<StackQuery>,
+) {
+    for MergeStacks {
+        requester,
+        from,
+        to,
+    } in events.drain()
+    {
+        let Ok(
+            [(from_asset, mut from_stack, from_perishable), (to_asset, mut to_stack, to_perishable)],
+        ) = stacks.get_many_mut([from, to])
+        else {
+            warn!("merge of {:?} into {:?} which are not stacks", from, to);
+            results.send(StackResult {
+                requester,
+                source: None,
+                target: None,
+                moved: 0.0,
+                overflow: 0.0,
+            });
+            continue;
+        };
+
+        let requested = from_stack.current;
+        let units = to_stack.current;
+        let moved = if from_asset == to_asset {
+            to_stack.merge(&mut from_stack)
+        } else {
+            warn!("merge of {:?} into {:?} of another item", from, to);
+            0.0
+        };
+        if let (Some(from_perishable), Some(mut to_perishable)) = (from_perishable, to_perishable) {
+            to_perishable.blend(units, moved, from_perishable.freshness);
+        }
+
+        results.send(StackResult {
+            requester,
+            source: Some(from),
+            target: Some(to),
+            moved,
+            overflow: requested - moved,
+        });
+    }
+}
+
+pub fn transfer_stack(
+    mut commands: Commands,
+    mut events: ResMut<Events<TransferStack>>,
+    mut results: EventWriter<StackResult>,
+    children: Query<&Children>,
+    mut set: ParamSet<(Admission, Query<StackQuery>)>,
+) {
+    set.p0().clear();
+    for TransferStack {
+        requester,
+        item,
+        amount,
+        from,
+        to,
+    } in events.drain()
+    {
+        if from == to {
+            warn!("transfer of {:?} from {:?} into itself", item, from);
+            results.send(StackResult {
+                requester,
+                source: None,
+                target: None,
+                moved: 0.0,
+                overflow: amount,
+            });
+            continue;
+        }
+
+        let stacks = set.p1();
+        let find = |container: Entity, filter: fn(&Consumable) -> bool| -> Vec<Entity> {
+            let Ok(children) = children.get(container) else {
+                return Vec::new();
+            };
+            children
+                .iter()
+                .copied()
+                .filter(|&entity| {
+                    stacks
+                        .get(entity)
+                        .is_ok_and(|(asset, stack, _)| *asset == item && filter(stack))
+                })
+                .collect()
+        };
+
+        let sources = find(from, |stack| !stack.is_empty());
+        let targets = find(to, |stack| !stack.is_full());
+
+        let stack = |entity| {
+            stacks
+                .get(entity)
+                .map(|(_, stack, _)| stack.clone())
+                .unwrap()
+        };
+        let available: f32 = sources.iter().map(|&source| stack(source).current).sum();
+        let maximum = sources.first().map_or(0.0, |&source| stack(source).maximum);
+        let space: f32 = targets.iter().map(|&target| stack(target).space()).sum();
+        let wanted = amount.clamp(0.0, available);
+
+        // what fits in the stacks already there, then in new ones
+        let mut accepted = 0.0;
+        if let Some(&source) = sources.first() {
+            let mut admission = set.p0();
+            let top_up = wanted.min(space);
+            let (fit, mut rejected) = admission.reserve(source, top_up, to, false);
+            accepted += fit;
+
+            while rejected.is_none() && maximum > 0.0 && wanted - accepted > 0.0 {
+                let units = (wanted - accepted).min(maximum);
+                let (fit, reason) = admission.reserve(source, units, to, true);
+                accepted += fit;
+                rejected = reason;
+            }
+            if let Some(reason) = rejected {
+                admission.reject(source, to, wanted - accepted, reason);
+            }
+        }
+
+        // draw from as many stacks as it takes
+        let mut stacks = set.p1();
+        let mut taken = 0.0;
+        let mut perishable: Option<Perishable> = None;
+        for &source in &sources {
+            if taken >= accepted {
+                break;
+            }
+            let (_, mut source_stack, source_perishable) = stacks.get_mut(source).unwrap();
+            let units = source_stack.take_amount(accepted - taken);
+            if let Some(source_perishable) = source_perishable {
+                let freshness = source_perishable.freshness;
+                perishable
+                    .get_or_insert_with(|| source_perishable.clone())
+                    .blend(taken, units, freshness);
+            }
+            taken += units;
+        }
+
+        // top up the stacks already there, then start new ones
+        let mut left = taken;
+        let mut target = None;
+        for &entity in &targets {
+            if left <= 0.0 {
+                break;
+            }
+            let (_, mut target_stack, target_perishable) = stacks.get_mut(entity).unwrap();
+            let units = target_stack.current;
+            let rest = target_stack.fill(left);
+            if let (Some(perishable), Some(mut target_perishable)) =
+                (&perishable, target_perishable)
+            {
+                target_perishable.blend(units, left - rest, perishable.freshness);
+            }
+            left = rest;
+            target.get_or_insert(entity);
+        }
+        while left > 0.0 && maximum > 0.0 {
+            let split = Consumable {
+                current: left.min(maximum),
+                maximum,
+            };
+            left -= split.current;
+
+            let mut entity = commands.spawn((item.clone(), split));
+            if let Some(perishable) = &perishable {
+                entity.insert(perishable.clone());
+            }
+            let entity = entity.id();
+            commands.entity(to).add_child(entity);
+            target.get_or_insert(entity);
+        }
+
+        results.send(StackResult {
+            requester,
+            source: sources.first().copied().filter(|_| taken > 0.0),
+            target,
+            moved: taken,
+            overflow: amount - taken,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mechanics::item::{ContainerCapacity, ContainerRejected, ItemWeight};
+
+    const ITEM: Handle<ItemAsset> = Handle::weak_from_u128(1);
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.init_resource::<Assets<ItemAsset>>()
+            .add_event::<SplitStack>()
+            .add_event::<TransferStack>()
+            .add_event::<StackResult>()
+            .add_event::<ContainerRejected>()
+            .add_systems(Update, (split_stack, transfer_stack));
+        app
+    }
+
+    fn container(app: &mut App, stacks: &[f32]) -> Entity {
+        let container = app.world.spawn_empty().id();
+        for &current in stacks {
+            let maximum = 10.0;
+            let stack = app.world.spawn((ITEM, Consumable { current, maximum }));
+            let stack = stack.id();
+            app.world.entity_mut(container).add_child(stack);
+        }
+        container
+    }
+
+    fn total(app: &App, container: Entity) -> f32 {
+        app.world
+            .get::<Children>(container)
+            .into_iter()
+            .flatten()
+            .filter_map(|&entity| app.world.get::<Consumable>(entity))
+            .map(|stack| stack.current)
+            .sum()
+    }
+
+    fn transfer(app: &mut App, amount: f32, from: Entity, to: Entity) -> StackResult {
+        app.world.send_event(TransferStack {
+            requester: Entity::PLACEHOLDER,
+            item: ITEM,
+            amount,
+            from,
+            to,
+        });
+        app.update();
+        let results = app.world.resource::<Events<StackResult>>();
+        *results.iter_current_update_events().last().unwrap()
+    }
+
+    #[test]
+    fn transfer_draws_from_several_stacks() {
+        let mut app = app();
+        let from = container(&mut app, &[3.0, 4.0]);
+        let to = container(&mut app, &[9.0]);
+
+        let result = transfer(&mut app, 6.0, from, to);
+
+        assert_eq!(result.moved, 6.0);
+        assert_eq!(result.overflow, 0.0);
+        assert_eq!(total(&app, from), 1.0);
+        assert_eq!(total(&app, to), 15.0);
+    }
+
+    #[test]
+    fn transfer_reports_what_is_missing() {
+        let mut app = app();
+        let from = container(&mut app, &[2.0]);
+        let to = container(&mut app, &[]);
+
+        let result = transfer(&mut app, 5.0, from, to);
+
+        assert_eq!(result.moved, 2.0);
+        assert_eq!(result.overflow, 3.0);
+        assert_eq!(total(&app, to), 2.0);
+    }
+
+    #[test]
+    fn transfer_into_the_same_container_moves_nothing() {
+        let mut app = app();
+        let container = container(&mut app, &[5.0]);
+
+        let result = transfer(&mut app, 2.0, container, container);
+
+        assert_eq!(result.moved, 0.0);
+        assert_eq!(total(&app, container), 5.0);
+    }
+
+    #[test]
+    fn transfer_blends_freshness_by_amount() {
+        let mut app = app();
+        let from = container(&mut app, &[4.0]);
+        let to = container(&mut app, &[4.0]);
+        for (container, freshness) in [(from, 1.0), (to, 0.5)] {
+            let stack = app.world.get::<Children>(container).unwrap()[0];
+            app.world.entity_mut(stack).insert(Perishable {
+                freshness,
+                ..default()
+            });
+        }
+
+        transfer(&mut app, 4.0, from, to);
+
+        let stack = app.world.get::<Children>(to).unwrap()[0];
+        let perishable = app.world.get::<Perishable>(stack).unwrap();
+        assert_eq!(perishable.freshness, 0.75);
+    }
+
+    #[test]
+    fn results_name_their_requester() {
+        let mut app = app();
+        let from = container(&mut app, &[5.0]);
+        let to = container(&mut app, &[]);
+        let (first, second) = (Entity::from_raw(1), Entity::from_raw(2));
+
+        for (requester, amount) in [(first, 2.0), (second, 9.0)] {
+            app.world.send_event(TransferStack {
+                requester,
+                item: ITEM,
+                amount,
+                from,
+                to,
+            });
+        }
+        app.update();
+
+        let results = app.world.resource::<Events<StackResult>>();
+        let results: Vec<_> = results
+            .iter_current_update_events()
+            .map(|result| (result.requester, result.moved, result.overflow))
+            .collect();
+        assert_eq!(results, [(first, 2.0, 0.0), (second, 3.0, 6.0)]);
+    }
+
+    fn limited(app: &mut App, weight: f32) -> Entity {
+        let to = container(app, &[]);
+        let capacity = ContainerCapacity {
+            weight,
+            ..default()
+        };
+        app.world.entity_mut(to).insert(capacity);
+        to
+    }
+
+    fn rejected(app: &App) -> Vec<(Entity, f32)> {
+        let rejected = app.world.resource::<Events<ContainerRejected>>();
+        rejected
+            .iter_current_update_events()
+            .map(|rejected| (rejected.container, rejected.amount))
+            .collect()
+    }
+
+    #[test]
+    fn transfer_stops_at_the_weight_limit() {
+        let mut app = app();
+        let from = container(&mut app, &[8.0]);
+        let stack = app.world.get::<Children>(from).unwrap()[0];
+        app.world.entity_mut(stack).insert(ItemWeight(1.0));
+        let to = limited(&mut app, 5.0);
+
+        let result = transfer(&mut app, 7.0, from, to);
+
+        assert_eq!(result.moved, 5.0);
+        assert_eq!(result.overflow, 2.0);
+        assert_eq!(rejected(&app), [(to, 2.0)]);
+        assert_eq!(total(&app, from), 3.0);
+        assert_eq!(total(&app, to), 5.0);
+    }
+
+    #[test]
+    fn split_leaves_what_does_not_fit() {
+        let mut app = app();
+        let from = container(&mut app, &[8.0]);
+        let stack = app.world.get::<Children>(from).unwrap()[0];
+        app.world.entity_mut(stack).insert(ItemWeight(1.0));
+        let to = limited(&mut app, 3.0);
+
+        app.world.send_event(SplitStack {
+            requester: Entity::PLACEHOLDER,
+            item: stack,
+            amount: 6.0,
+            to,
+        });
+        app.update();
+
+        let results = app.world.resource::<Events<StackResult>>();
+        let result = *results.iter_current_update_events().last().unwrap();
+        assert_eq!(result.moved, 3.0);
+        assert_eq!(result.overflow, 3.0);
+        assert_eq!(rejected(&app), [(to, 3.0)]);
+        assert_eq!(total(&app, from), 5.0);
+        assert_eq!(total(&app, to), 3.0);
+    }
+}
@@ -0,0 +1,633 @@
+use super::capacity::{
+    ContainerCapacity, ContainerLoad, ItemSize, ItemVolume, ItemWeight, Overfill,
+};
+use super::{Consumable, ItemAsset, Perishable};
+use bevy::ecs::system::lifetimeless::{Read, Write};
+use bevy::prelude::*;
+use bevy::utils::{
+    thiserror::{self, Error},
+    HashMap,
+};
+
+/// A single step of a [`ContainerTransaction`], all amounts are stack units.
+#[derive(Clone, Debug)]
+pub enum TransactionOp {
+    /// Moves units of an item from one container to another.
+    Move {
+        item: Handle<ItemAsset>,
+        amount: f32,
+        from: Entity,
+        to: Entity,
+    },
+    /// Adds new units of an item to a container.
+    Create {
+        item: Handle<ItemAsset>,
+        amount: f32,
+        to: Entity,
+    },
+    /// Removes units of an item from a container.
+    Destroy {
+        item: Handle<ItemAsset>,
+        amount: f32,
+        from: Entity,
+    },
+}
+
+/// Several item moves applied all together or not at all.
+///
+/// Works on [`Consumable`] stacks: taking empties the first stacks found,
+/// putting tops up existing stacks before starting new ones.
+#[derive(Event, Clone, Debug)]
+pub struct ContainerTransaction {
+    /// Who asked for the transaction, echoed in [`TransactionResult`].
+    pub requester: Entity,
+    pub ops: Vec<TransactionOp>,
+}
+
+impl ContainerTransaction {
+    pub fn new(requester: Entity) -> Self {
+        Self {
+            requester,
+            ops: Vec::new(),
+        }
+    }
+
+    pub fn with_move(
+        mut self,
+        item: &Handle<ItemAsset>,
+        amount: f32,
+        from: Entity,
+        to: Entity,
+    ) -> Self {
+        self.ops.push(TransactionOp::Move {
+            item: item.clone(),
+            amount,
+            from,
+            to,
+        });
+        self
+    }
+
+    pub fn with_create(mut self, item: &Handle<ItemAsset>, amount: f32, to: Entity) -> Self {
+        self.ops.push(TransactionOp::Create {
+            item: item.clone(),
+            amount,
+            to,
+        });
+        self
+    }
+
+    pub fn with_destroy(mut self, item: &Handle<ItemAsset>, amount: f32, from: Entity) -> Self {
+        self.ops.push(TransactionOp::Destroy {
+            item: item.clone(),
+            amount,
+            from,
+        });
+        self
+    }
+}
+
+#[derive(Event, Clone, Debug)]
+pub struct TransactionResult {
+    pub requester: Entity,
+    pub result: Result<(), TransactionError>,
+}
+
+/// Why a [`ContainerTransaction`] was not applied.
+#[derive(Error, Clone, Debug)]
+pub enum TransactionError {
+    #[error("container {container:?} lacks {missing} units of the item")]
+    NotEnough {
+        container: Entity,
+        item: AssetId<ItemAsset>,
+        missing: f32,
+    },
+    #[error("container {container:?} has no room ({reason:?}) for {overflow} units of the item")]
+    NoRoom {
+        container: Entity,
+        item: AssetId<ItemAsset>,
+        overflow: f32,
+        reason: Overfill,
+    },
+    #[error("item is not a loaded stack")]
+    NotAStack { item: AssetId<ItemAsset> },
+}
+
+type ContainerQuery = (Option<Read<ContainerCapacity>>, Option<Read<Children>>);
+type UnitQuery = (Option<Read<ItemWeight>>, Option<Read<ItemVolume>>);
+type StackQuery = (
+    Read<Handle<ItemAsset>>,
+    Write<Consumable>,
+    Option<Write<Perishable>>,
+);
+type ReadStackQuery = (
+    Read<Handle<ItemAsset>>,
+    Read<Consumable>,
+    Option<Read<Perishable>>,
+);
+
+/// Stack amounts as they would be after the transaction.
+///
+/// New stacks and container loads carry over to the next transactions of the same run,
+/// the new stacks are only spawned once all of them are applied.
+#[derive(Clone, Default)]
+struct Plan {
+    stacks: HashMap<Entity, f32>,
+    freshness: HashMap<Entity, f32>,
+    created: Vec<(Entity, Handle<ItemAsset>, Consumable, Option<Perishable>)>,
+    loads: HashMap<Entity, Option<(ContainerCapacity, ContainerLoad)>>,
+}
+
+/// A stack already in a container or one created earlier in the run.
+#[derive(Clone, Copy)]
+enum PlannedStack {
+    Spawned(Entity),
+    Created(usize),
+}
+
+/// Planned amount and freshness of a stack.
+struct StackPlan {
+    stack: PlannedStack,
+    current: f32,
+    maximum: f32,
+    freshness: Option<f32>,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn apply_transactions(
+    mut commands: Commands,
+    mut events: ResMut<Events<ContainerTransaction>>,
+    mut results: EventWriter<TransactionResult>,
+    assets: Res<Assets<ItemAsset>>,
+    containers: Query<ContainerQuery>,
+    sizes: Query<UnitQuery, With<Handle<ItemAsset>>>,
+    parents: Query<Read<Parent>>,
+    mut stacks: Query<StackQuery>,
+) {
+    let mut batch = Plan::default();
+    for ContainerTransaction { requester, ops } in events.drain() {
+        let mut plan = batch.clone();
+        let readonly = stacks.to_readonly();
+        let ctx = Context {
+            assets: &assets,
+            containers: &containers,
+            sizes: &sizes,
+            parents: &parents,
+            stacks: &readonly,
+        };
+
+        let result = ops.iter().try_for_each(|op| match op {
+            TransactionOp::Move {
+                item,
+                amount,
+                from,
+                to,
+            } => {
+                let freshness = ctx.take(&mut plan, item, *amount, *from)?;
+                ctx.put(&mut plan, item, *amount, *to, freshness)
+            }
+            TransactionOp::Create { item, amount, to } => {
+                ctx.put(&mut plan, item, *amount, *to, None)
+            }
+            TransactionOp::Destroy { item, amount, from } => {
+                ctx.take(&mut plan, item, *amount, *from).map(|_| ())
+            }
+        });
+
+        if result.is_ok() {
+            for (entity, current) in plan.stacks.drain() {
+                if let Ok((_, mut stack, _)) = stacks.get_mut(entity) {
+                    stack.current = current;
+                }
+            }
+            for (entity, freshness) in plan.freshness.drain() {
+                if let Ok((_, _, Some(mut perishable))) = stacks.get_mut(entity) {
+                    perishable.freshness = freshness;
+                }
+            }
+            batch = plan;
+        }
+
+        results.send(TransactionResult { requester, result });
+    }
+
+    // spawned with their own amount, the template does not overwrite it
+    for (container, item, stack, perishable) in batch.created {
+        if stack.current > 0.0 {
+            let mut entity = commands.spawn((item, stack));
+            if let Some(perishable) = perishable {
+                entity.insert(perishable);
+            }
+            let entity = entity.id();
+            commands.entity(container).add_child(entity);
+        }
+    }
+}
+
+impl Plan {
+    fn set(&mut self, stack: PlannedStack, current: f32) {
+        match stack {
+            PlannedStack::Spawned(entity) => {
+                self.stacks.insert(entity, current);
+            }
+            PlannedStack::Created(i) => self.created[i].2.current = current,
+        }
+    }
+
+    fn set_freshness(&mut self, stack: PlannedStack, freshness: f32) {
+        match stack {
+            PlannedStack::Spawned(entity) => {
+                self.freshness.insert(entity, freshness);
+            }
+            PlannedStack::Created(i) => {
+                if let Some(perishable) = &mut self.created[i].3 {
+                    perishable.freshness = freshness;
+                }
+            }
+        }
+    }
+}
+
+struct Context<'a, 'w, 's> {
+    assets: &'a Assets<ItemAsset>,
+    containers: &'a Query<'w, 's, ContainerQuery>,
+    sizes: &'a Query<'w, 's, UnitQuery, With<Handle<ItemAsset>>>,
+    parents: &'a Query<'w, 's, Read<Parent>>,
+    stacks: &'a Query<'w, 's, ReadStackQuery>,
+}
+
+impl Context<'_, '_, '_> {
+    fn unit(&self, item: &Handle<ItemAsset>) -> ItemSize {
+        self.assets
+            .get(item)
+            .map(ItemSize::unit_of)
+            .unwrap_or_default()
+    }
+
+    fn children(&self, container: Entity) -> impl Iterator<Item = Entity> + '_ {
+        let children = self.containers.get(container).ok().and_then(|(_, c)| c);
+        children.into_iter().flatten().copied()
+    }
+
+    fn load<'p>(
+        &self,
+        plan: &'p mut Plan,
+        container: Entity,
+    ) -> &'p mut Option<(ContainerCapacity, ContainerLoad)> {
+        plan.loads.entry(container).or_insert_with(|| {
+            let (capacity, _) = self.containers.get(container).ok()?;
+            let capacity = capacity?;
+
+            Some((*capacity, self.contents(container)))
+        })
+    }
+
+    /// What is in the container, a container item weighing as much as its contents.
+    fn contents(&self, container: Entity) -> ContainerLoad {
+        let mut load = ContainerLoad::default();
+        for child in self.children(container) {
+            if !self.sizes.contains(child) {
+                continue;
+            }
+
+            // the planned amounts are accounted for by `take` and `put`
+            let stack = self.stacks.get(child).ok().map(|(_, stack, _)| stack);
+            let (weight, volume) = self.sizes.get(child).unwrap_or_default();
+            let size = ItemSize {
+                units: stack.map_or(1.0, |c| c.current.max(0.0)),
+                weight: weight.map_or(0.0, |w| w.0),
+                volume: volume.map_or(0.0, |v| v.0),
+            };
+            load.add(&size, true);
+
+            let inside = self.contents(child);
+            load.weight += inside.weight;
+            load.volume += inside.volume;
+        }
+        load
+    }
+
+    /// The container and the containers holding it, which all carry what is put in it.
+    fn enclosing(&self, container: Entity) -> Vec<Entity> {
+        std::iter::once(container)
+            .chain(self.parents.iter_ancestors(container))
+            .collect()
+    }
+
+    /// Stacks of the item in the container with their planned amount.
+    fn stacks_of(
+        &self,
+        plan: &Plan,
+        item: &Handle<ItemAsset>,
+        container: Entity,
+    ) -> Vec<StackPlan> {
+        let spawned = self.children(container).filter_map(|entity| {
+            let (asset, stack, perishable) = self.stacks.get(entity).ok()?;
+            let freshness = perishable.map(|perishable| {
+                let planned = plan.freshness.get(&entity).copied();
+                planned.unwrap_or(perishable.freshness)
+            });
+            (asset == item).then(|| StackPlan {
+                stack: PlannedStack::Spawned(entity),
+                current: plan.stacks.get(&entity).copied().unwrap_or(stack.current),
+                maximum: stack.maximum,
+                freshness,
+            })
+        });
+        let created = plan.created.iter().enumerate().filter_map(|(i, created)| {
+            let (to, asset, stack, perishable) = created;
+            (*to == container && asset == item).then(|| StackPlan {
+                stack: PlannedStack::Created(i),
+                current: stack.current,
+                maximum: stack.maximum,
+                freshness: perishable.as_ref().map(|perishable| perishable.freshness),
+            })
+        });
+        spawned.chain(created).collect()
+    }
+
+    /// Takes the units from the stacks in order, returns the freshness they had together.
+    fn take(
+        &self,
+        plan: &mut Plan,
+        item: &Handle<ItemAsset>,
+        amount: f32,
+        from: Entity,
+    ) -> Result<Option<f32>, TransactionError> {
+        let mut left = amount;
+        let mut freshness: Option<(f32, f32)> = None;
+        for planned in self.stacks_of(plan, item, from) {
+            let taken = left.min(planned.current.max(0.0));
+            if taken > 0.0 {
+                plan.set(planned.stack, planned.current - taken);
+                left -= taken;
+
+                if let Some(taken_freshness) = planned.freshness {
+                    freshness = Some(match freshness {
+                        Some((f, units)) => (
+                            Perishable::mix(f, units, taken_freshness, taken),
+                            units + taken,
+                        ),
+                        None => (taken_freshness, taken),
+                    });
+                }
+            }
+        }
+
+        if left > 0.0 {
+            return Err(TransactionError::NotEnough {
+                container: from,
+                item: item.id(),
+                missing: left,
+            });
+        }
+
+        let unit = self.unit(item);
+        for container in self.enclosing(from) {
+            if let Some((_, load)) = self.load(plan, container) {
+                load.weight -= amount * unit.weight;
+                load.volume -= amount * unit.volume;
+            }
+        }
+
+        Ok(freshness.map(|(freshness, _)| freshness))
+    }
+
+    fn put(
+        &self,
+        plan: &mut Plan,
+        item: &Handle<ItemAsset>,
+        amount: f32,
+        to: Entity,
+        freshness: Option<f32>,
+    ) -> Result<(), TransactionError> {
+        let unit = self.unit(item);
+        let asset = self.assets.get(item);
+        // units without a known freshness come fresh from the template
+        let perishable = asset
+            .and_then(ItemAsset::get::<Perishable>)
+            .map(|template| {
+                let mut perishable = template.clone();
+                if let Some(freshness) = freshness {
+                    perishable.freshness = freshness;
+                }
+                perishable
+            });
+        let maximum = asset
+            .and_then(ItemAsset::get::<Consumable>)
+            .map(|stack| stack.maximum)
+            .filter(|maximum| *maximum > 0.0)
+            .ok_or(TransactionError::NotAStack { item: item.id() })?;
+
+        let mut left = amount;
+        for planned in self.stacks_of(plan, item, to) {
+            let added = left.min((planned.maximum - planned.current).max(0.0));
+            if added > 0.0 {
+                plan.set(planned.stack, planned.current + added);
+                left -= added;
+
+                if let (Some(current), Some(perishable)) = (planned.freshness, &perishable) {
+                    let units = planned.current.max(0.0);
+                    let blended = Perishable::mix(current, units, perishable.freshness, added);
+                    plan.set_freshness(planned.stack, blended);
+                }
+            }
+        }
+
+        let new_stacks = (left / maximum).ceil() as usize;
+
+        let size = ItemSize {
+            units: amount,
+            ..unit
+        };
+        // the containers around `to` carry the units as well
+        let containers = self.enclosing(to);
+        for &container in &containers {
+            let Some((capacity, load)) = self.load(plan, container) else {
+                continue;
+            };
+
+            let no_room = |overflow, reason| TransactionError::NoRoom {
+                container,
+                item: item.id(),
+                overflow,
+                reason,
+            };
+
+            if container == to && load.slots.saturating_add(new_stacks) > capacity.slots {
+                return Err(no_room(left, Overfill::Slots));
+            }
+            if let (units, Some(reason)) = capacity.fit(load, &size, false) {
+                return Err(no_room(amount - units, reason));
+            }
+        }
+        for container in containers {
+            if let Some((_, load)) = self.load(plan, container) {
+                load.add(&size, false);
+                if container == to {
+                    load.slots += new_stacks;
+                }
+            }
+        }
+
+        while left > 0.0 {
+            let current = left.min(maximum);
+            left -= current;
+            plan.created.push((
+                to,
+                item.clone(),
+                Consumable { current, maximum },
+                perishable.clone(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Setup {
+        app: App,
+        item: Handle<ItemAsset>,
+        container: Entity,
+    }
+
+    fn setup(slots: usize) -> Setup {
+        let mut app = App::new();
+        app.init_resource::<Assets<ItemAsset>>()
+            .add_event::<ContainerTransaction>()
+            .add_event::<TransactionResult>()
+            .add_systems(Update, apply_transactions);
+
+        let stack = Consumable {
+            current: 0.0,
+            maximum: 10.0,
+        };
+        let item = app
+            .world
+            .resource_mut::<Assets<ItemAsset>>()
+            .add(ItemAsset {
+                components: vec![Box::new(stack)],
+                ..default()
+            });
+        let capacity = ContainerCapacity {
+            slots,
+            ..ContainerCapacity::UNLIMITED
+        };
+        let container = app.world.spawn(capacity).id();
+
+        Setup {
+            app,
+            item,
+            container,
+        }
+    }
+
+    fn results(app: &App) -> Vec<bool> {
+        let results = app.world.resource::<Events<TransactionResult>>();
+        results
+            .iter_current_update_events()
+            .map(|event| event.result.is_ok())
+            .collect()
+    }
+
+    fn stacks(app: &App, container: Entity) -> Vec<f32> {
+        let children = app.world.get::<Children>(container).into_iter().flatten();
+        children
+            .filter_map(|&entity| app.world.get::<Consumable>(entity))
+            .map(|stack| stack.current)
+            .collect()
+    }
+
+    #[test]
+    fn creates_in_one_frame_share_the_slot_limit() {
+        let Setup {
+            mut app,
+            item,
+            container,
+        } = setup(1);
+        let requester = Entity::PLACEHOLDER;
+
+        for amount in [6.0, 6.0] {
+            let create = ContainerTransaction::new(requester).with_create(&item, amount, container);
+            app.world.send_event(create);
+        }
+        app.update();
+
+        // the second one needs a new stack for the units past the first one's maximum
+        assert_eq!(results(&app), [true, false]);
+        assert_eq!(stacks(&app, container), [6.0]);
+    }
+
+    #[test]
+    fn later_transactions_see_stacks_created_before() {
+        let Setup {
+            mut app,
+            item,
+            container,
+        } = setup(1);
+        let requester = Entity::PLACEHOLDER;
+
+        let create = ContainerTransaction::new(requester).with_create(&item, 6.0, container);
+        let top_up = ContainerTransaction::new(requester).with_create(&item, 3.0, container);
+        let destroy = ContainerTransaction::new(requester).with_destroy(&item, 5.0, container);
+        for transaction in [create, top_up, destroy] {
+            app.world.send_event(transaction);
+        }
+        app.update();
+
+        assert_eq!(results(&app), [true, true, true]);
+        assert_eq!(stacks(&app, container), [4.0]);
+    }
+
+    #[test]
+    fn bags_count_against_the_inventory_holding_them() {
+        let Setup {
+            mut app,
+            container: inventory,
+            ..
+        } = setup(usize::MAX);
+        app.world.entity_mut(inventory).insert(ContainerCapacity {
+            weight: 5.0,
+            ..ContainerCapacity::UNLIMITED
+        });
+
+        let stack = Consumable {
+            current: 0.0,
+            maximum: 10.0,
+        };
+        let mut assets = app.world.resource_mut::<Assets<ItemAsset>>();
+        let item = assets.add(ItemAsset {
+            components: vec![Box::new(stack.clone()), Box::new(ItemWeight(1.0))],
+            ..default()
+        });
+        let bag = assets.add(ItemAsset::default());
+
+        let carried = Consumable {
+            current: 3.0,
+            ..stack
+        };
+        let carried = app
+            .world
+            .spawn((item.clone(), carried, ItemWeight(1.0)))
+            .id();
+        let bag = app.world.spawn((bag, ContainerCapacity::UNLIMITED)).id();
+        app.world
+            .entity_mut(inventory)
+            .push_children(&[carried, bag]);
+
+        let requester = Entity::PLACEHOLDER;
+        for amount in [4.0, 2.0] {
+            let create = ContainerTransaction::new(requester).with_create(&item, amount, bag);
+            app.world.send_event(create);
+        }
+        app.update();
+
+        // the bag holds any weight, the inventory only 2 more units
+        assert_eq!(results(&app), [false, true]);
+        assert_eq!(stacks(&app, bag), [2.0]);
+    }
+}
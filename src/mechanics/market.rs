@@ -1,64 +1,253 @@
 use crate::{
-    character::{FindAndMove, Inventory},
+    character::{CharacterController, FindAndMove, Inventory},
     game_state::GameState,
     loading::ItemDatabase,
 };
-use bevy::prelude::*;
+use bevy::{ecs::system::lifetimeless::Read, prelude::*, utils::HashMap};
 use big_brain::prelude::*;
+use std::any::TypeId;
 
-use super::item::{Consumable, ReadConsumable, WriteConsumable};
+use super::item::{
+    BaseValue, Consumable, ContainerTransaction, ItemAsset, ItemFilter, ReadCapacity,
+    ReadConsumable, ReadContainer, RestoreFatigue, RestoreHunger, TransactionResult,
+};
+use super::usage::{Hunger, HUNGER_THRESHOLD};
+use super::Fatigue;
+
+mod orders;
+mod pricing;
+mod stock;
+
+pub use self::orders::{
+    expire_orders, match_orders, quote_market_orders, Order, OrderBook, Side, ORDER_LIFETIME,
+};
+pub use self::pricing::{
+    decay_trade_volume, record_prices, Goods, MarketGoods, PriceHistory, PriceSample,
+    PricingConfig, PRICE_HISTORY_LENGTH, PRICE_SAMPLE_INTERVAL,
+};
+pub use self::stock::{
+    count_market_stock, restock_markets, MarketStock, StockRule, StockRules, MARKET_CAPACITY,
+    MARKET_FUNDS,
+};
+
+/// Prices this much above the base value make characters sell before their stacks are full.
+pub const EAGER_PRICE_RATIO: f32 = 1.25;
+/// Fatigue above which characters buy a remedy, ahead of needing it.
+pub const STOCK_UP_FATIGUE: f32 = 40.0;
+/// Units bought at once.
+pub const BUY_AMOUNT: f32 = 1.0;
 
-#[derive(Component, Clone)]
+#[derive(Component, Clone, TypePath)]
 pub struct Market;
 
 pub struct MarketPlugin;
 
 impl Plugin for MarketPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            PreUpdate,
-            (
-                (sell_action, FindAndMove::<Market>::system).in_set(BigBrainSet::Actions),
-                sell_need_scorer.in_set(BigBrainSet::Scorers),
+        app.register_type::<MarketStock>()
+            .init_resource::<PricingConfig>()
+            .init_resource::<PriceHistory>()
+            .init_resource::<StockRules>()
+            .add_systems(
+                PreUpdate,
+                (
+                    (sell_action, buy_action, FindAndMove::<Market>::system)
+                        .in_set(BigBrainSet::Actions),
+                    (sell_need_scorer, buy_need_scorer).in_set(BigBrainSet::Scorers),
+                )
+                    .run_if(in_state(GameState::Playing)),
             )
-                .run_if(in_state(GameState::Playing)),
-        );
+            .add_systems(
+                Update,
+                (
+                    restock_markets,
+                    count_market_stock,
+                    quote_market_orders,
+                    match_orders,
+                    expire_orders,
+                    decay_trade_volume,
+                    record_prices,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Playing)),
+            );
     }
 }
 
+/// The market closest to `position`.
+pub fn nearest_market<'a>(
+    markets: impl IntoIterator<Item = (Entity, &'a Transform)>,
+    position: Vec3,
+) -> Option<Entity> {
+    markets
+        .into_iter()
+        .min_by(|(_, a), (_, b)| {
+            let a = (a.translation - position).length_squared();
+            let b = (b.translation - position).length_squared();
+            f32::total_cmp(&a, &b)
+        })
+        .map(|(entity, _)| entity)
+}
+
 /// Selling 💰
 #[derive(Component, Clone, ActionSpawn)]
 pub struct Sell;
 
+/// Stacks with what a unit of them is worth, items without a [`BaseValue`] do not sell.
+pub type SellableQuery = (Read<Consumable>, Option<Read<BaseValue>>);
+
+/// Part of a stack a market takes.
+pub struct SaleOffer<'a> {
+    pub item: &'a Handle<ItemAsset>,
+    pub amount: f32,
+    /// What the market pays per unit.
+    pub price: f32,
+    /// What a unit is usually worth.
+    pub base: f32,
+}
+
+/// What the market takes of the food in a container, within its stock limits.
+pub fn sale_offers<'a>(
+    sellable: &'a ReadContainer<SellableQuery>,
+    container: Entity,
+    goods: &MarketGoods,
+    config: &PricingConfig,
+    rules: &StockRules,
+    items: &ItemDatabase,
+) -> Vec<SaleOffer<'a>> {
+    let mut taken: HashMap<AssetId<ItemAsset>, f32> = HashMap::new();
+    let mut offers = Vec::new();
+
+    for (item, (stack, value)) in sellable.iter(ItemFilter::Tag("food"), container) {
+        let Some(&BaseValue(base)) = value else {
+            continue;
+        };
+
+        let stock = goods.get(item);
+        let taken = taken.entry(item.id()).or_default();
+        let amount = stack
+            .current
+            .min(rules.limit(items, item) - stock.stock - *taken);
+        if amount > 0.0 {
+            *taken += amount;
+            offers.push(SaleOffer {
+                item,
+                amount,
+                price: config.sell_price(&stock, base),
+                base,
+            });
+        }
+    }
+
+    offers
+}
+
+/// Orders a trading character places once what they set aside is in the escrow.
+pub struct PendingOrders {
+    market: Entity,
+    orders: Vec<Order>,
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn sell_action(
-    mut commands: Commands,
-    mut actors: Query<&Inventory>,
-    mut query: Query<ActionQuery, With<Sell>>,
+    time: Res<Time<Virtual>>,
+    actors: Query<(&Inventory, &Transform)>,
+    mut query: Query<(Entity, ActionQuery), With<Sell>>,
+    mut pending: Local<HashMap<Entity, PendingOrders>>,
 
+    mut markets: Query<(Entity, &Transform, &MarketGoods, &mut OrderBook), With<Market>>,
+    config: Res<PricingConfig>,
+    rules: Res<StockRules>,
     items: Res<ItemDatabase>,
-    children: Query<&Children>,
-    mut consumable: WriteConsumable,
+    sellable: ReadContainer<SellableQuery>,
+    mut transactions: EventWriter<ContainerTransaction>,
+    mut results: EventReader<TransactionResult>,
 ) {
-    for mut action in &mut query {
-        let inventory = actors.get_mut(action.actor()).unwrap();
-        let container = children.get(inventory.container).ok();
+    // goods in the escrow get their asks, even when the action was cancelled meanwhile
+    let mut placed: HashMap<Entity, bool> = HashMap::new();
+    for TransactionResult { requester, result } in results.read() {
+        let Some(PendingOrders { market, orders }) = pending.remove(requester) else {
+            continue;
+        };
+        match result {
+            Ok(()) => {
+                debug!("Asks placed!");
+                if let Ok((_, _, _, mut book)) = markets.get_mut(market) {
+                    for order in orders {
+                        book.place(order);
+                    }
+                }
+            }
+            Err(err) => debug!("Selling failed: {}", err),
+        }
+        placed.insert(*requester, result.is_ok());
+    }
+
+    for (entity, mut action) in &mut query {
+        let (inventory, transform) = actors.get(action.actor()).unwrap();
 
         if action.is_executing() {
-            fn sell_food(mut food: Mut<Consumable>, mut money: Mut<Consumable>) -> f32 {
-                let amount = food.take();
-                money.current += amount;
-                amount
-            }
+            if let Some(ok) = placed.remove(&entity) {
+                if ok {
+                    action.success();
+                } else {
+                    action.failure();
+                }
+            } else if !pending.contains_key(&entity) {
+                let Some(market) = nearest_market(
+                    markets
+                        .iter()
+                        .map(|(market, transform, _, _)| (market, transform)),
+                    transform.translation,
+                ) else {
+                    debug!("No market to sell at!");
+                    action.failure();
+                    continue;
+                };
+                let (_, _, goods, book) = markets.get(market).unwrap();
+
+                let offers = sale_offers(
+                    &sellable,
+                    inventory.container,
+                    goods,
+                    &config,
+                    &rules,
+                    &items,
+                );
+                if offers.is_empty() {
+                    debug!("Nothing to sell!");
+                    action.failure();
+                    continue;
+                }
+
+                // the food waits in the escrow until someone takes the ask
+                let mut transaction = ContainerTransaction::new(entity);
+                let mut orders = Vec::new();
+                for offer in offers {
+                    transaction = transaction.with_move(
+                        offer.item,
+                        offer.amount,
+                        inventory.container,
+                        book.escrow,
+                    );
+                    let price = book
+                        .best(Side::Bid, offer.item)
+                        .map_or(offer.price, |bid| bid.price.max(offer.price));
+                    orders.push(
+                        Order::new(
+                            action.actor(),
+                            Side::Ask,
+                            offer.item,
+                            offer.amount,
+                            price,
+                            time.elapsed_seconds() + ORDER_LIFETIME,
+                        )
+                        .with_escrow(offer.amount),
+                    );
+                }
 
-            if let Some(amount) =
-                consumable.transfer(container, &items.raw_food, &items.money, sell_food)
-            {
-                debug!("Sold! amount: {}", amount);
-                action.success();
-            } else {
-                // add empty money and try next frame
-                let food = commands.spawn(items.money.clone()).id();
-                commands.entity(inventory.container).add_child(food);
+                transactions.send(transaction);
+                pending.insert(entity, PendingOrders { market, orders });
             }
         }
 
@@ -72,18 +261,293 @@ pub fn sell_action(
 #[derive(Component, Clone, Default, ScorerSpawn)]
 pub struct SellNeedScorer;
 
+#[allow(clippy::too_many_arguments)]
 pub fn sell_need_scorer(
-    actors: Query<&Inventory>,
+    actors: Query<(&Inventory, &Transform)>,
     mut query: Query<ScorerQuery, With<SellNeedScorer>>,
 
+    markets: Query<(Entity, &Transform, &MarketGoods, &OrderBook), With<Market>>,
+    config: Res<PricingConfig>,
+    rules: Res<StockRules>,
     items: Res<ItemDatabase>,
-    children: Query<&Children>,
-    consumable: ReadConsumable,
+    sellable: ReadContainer<SellableQuery>,
+    capacity: ReadCapacity,
 ) {
+    let Some(raw_food) = items.get("raw_food") else {
+        return;
+    };
+
     for mut score in &mut query {
-        let inventory = actors.get(score.actor()).expect("actor");
-        let children = children.get(inventory.container).ok();
-        let has_enough = consumable.get_or(&items.raw_food, children, false, Consumable::is_full);
-        score.set(if has_enough { 0.6 } else { 0.0 });
+        let (inventory, transform) = actors.get(score.actor()).expect("actor");
+        // nothing more to sell while the last asks are still open
+        let offers = nearest_market(
+            markets
+                .iter()
+                .map(|(market, transform, _, _)| (market, transform)),
+            transform.translation,
+        )
+        .and_then(|market| markets.get(market).ok())
+        .filter(|(_, _, _, book)| !book.has_open(score.actor(), Side::Ask))
+        .map(|(_, _, goods, _)| {
+            sale_offers(
+                &sellable,
+                inventory.container,
+                goods,
+                &config,
+                &rules,
+                &items,
+            )
+        })
+        .unwrap_or_default();
+
+        let food = ItemFilter::Tag("food");
+        let full = sellable
+            .iter(food, inventory.container)
+            .any(|(_, (stack, _))| stack.is_full());
+        let amount: f32 = offers.iter().map(|offer| offer.amount).sum();
+        let worth: f32 = offers.iter().map(|offer| offer.amount * offer.price).sum();
+        let base: f32 = offers.iter().map(|offer| offer.amount * offer.base).sum();
+        // how the market values the food against what it is usually worth
+        let ratio = if base > 0.0 { worth / base } else { 1.0 };
+
+        // sell a full stack, or what the market takes once the backpack is full or prices are good
+        let has_enough = amount > 0.0
+            && (full
+                || ratio >= EAGER_PRICE_RATIO
+                || capacity.room_for(inventory.container, raw_food, false) <= 0.0);
+        score.set(if has_enough {
+            (0.6 * ratio).clamp(0.3, 0.9)
+        } else {
+            0.0
+        });
+    }
+}
+
+/// Buying 🛒
+#[derive(Component, Clone, ActionSpawn)]
+pub struct Buy;
+
+pub type ShopperQuery = (
+    Read<Inventory>,
+    Read<Transform>,
+    Read<CharacterController>,
+    Option<Read<Hunger>>,
+    Option<Read<Fatigue>>,
+);
+
+/// Whether a character wants items with the effect, carried or not.
+pub fn in_need(hunger: Option<&Hunger>, fatigue: Option<&Fatigue>, need: TypeId) -> bool {
+    if need == TypeId::of::<RestoreHunger>() {
+        hunger.is_some_and(|hunger| hunger.current >= HUNGER_THRESHOLD)
+    } else if need == TypeId::of::<RestoreFatigue>() {
+        fatigue.is_some_and(|fatigue| fatigue.current >= STOCK_UP_FATIGUE)
+    } else {
+        false
+    }
+}
+
+/// Item effect a character would go shopping for, the most pressing need first.
+pub fn shopping_need(
+    hunger: Option<&Hunger>,
+    fatigue: Option<&Fatigue>,
+    carries: impl Fn(ItemFilter) -> bool,
+) -> Option<TypeId> {
+    [
+        TypeId::of::<RestoreHunger>(),
+        TypeId::of::<RestoreFatigue>(),
+    ]
+    .into_iter()
+    .find(|&need| in_need(hunger, fatigue, need) && !carries(ItemFilter::Component(need)))
+}
+
+/// The cheapest ask for an item with the component, with its unit price.
+pub fn cheapest_offer<'a>(
+    need: TypeId,
+    book: &'a OrderBook,
+    assets: &Assets<ItemAsset>,
+) -> Option<(&'a Handle<ItemAsset>, f32)> {
+    book.orders()
+        .filter(|order| order.side == Side::Ask && order.quantity >= BUY_AMOUNT)
+        .filter(|order| {
+            assets
+                .get(&order.item)
+                .is_some_and(|asset| asset.contains(need))
+        })
+        .map(|order| (&order.item, order.price))
+        .min_by(|(_, a), (_, b)| f32::total_cmp(a, b))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn buy_action(
+    time: Res<Time<Virtual>>,
+    actors: Query<ShopperQuery>,
+    mut query: Query<(Entity, ActionQuery), With<Buy>>,
+    mut pending: Local<HashMap<Entity, PendingOrders>>,
+
+    mut markets: Query<(Entity, &Transform, &mut OrderBook), With<Market>>,
+    items: Res<ItemDatabase>,
+    assets: Res<Assets<ItemAsset>>,
+    carried: ReadContainer<Entity>,
+    wallet: ReadConsumable,
+    mut transactions: EventWriter<ContainerTransaction>,
+    mut results: EventReader<TransactionResult>,
+) {
+    let Some(money) = items.get("money") else {
+        return;
+    };
+
+    // money in the escrow gets its bid, even when the action was cancelled meanwhile
+    let mut placed: HashMap<Entity, bool> = HashMap::new();
+    for TransactionResult { requester, result } in results.read() {
+        let Some(PendingOrders { market, orders }) = pending.remove(requester) else {
+            continue;
+        };
+        match result {
+            Ok(()) => {
+                debug!("Bid placed!");
+                if let Ok((_, _, mut book)) = markets.get_mut(market) {
+                    for order in orders {
+                        book.place(order);
+                    }
+                }
+            }
+            Err(err) => debug!("Buying failed: {}", err),
+        }
+        placed.insert(*requester, result.is_ok());
+    }
+
+    for (entity, mut action) in &mut query {
+        let (inventory, transform, _, hunger, fatigue) = actors.get(action.actor()).unwrap();
+
+        if action.is_executing() {
+            if let Some(ok) = placed.remove(&entity) {
+                if ok {
+                    action.success();
+                } else {
+                    action.failure();
+                }
+            } else if !pending.contains_key(&entity) {
+                let carries = |filter: ItemFilter| {
+                    carried
+                        .find_recursive(filter, inventory.container, |_| ())
+                        .is_some()
+                };
+                let Some(need) = shopping_need(hunger, fatigue, carries) else {
+                    debug!("Nothing to buy!");
+                    action.failure();
+                    continue;
+                };
+
+                let Some(market) = nearest_market(
+                    markets
+                        .iter()
+                        .map(|(market, transform, _)| (market, transform)),
+                    transform.translation,
+                ) else {
+                    debug!("No market to buy at!");
+                    action.failure();
+                    continue;
+                };
+                let (_, _, book) = markets.get(market).unwrap();
+
+                let Some((item, price)) = cheapest_offer(need, book, &assets) else {
+                    debug!("Out of stock!");
+                    action.failure();
+                    continue;
+                };
+
+                let cost = price * BUY_AMOUNT;
+                if wallet.sum(money.into(), inventory.container) < cost {
+                    debug!("Can't afford it!");
+                    action.failure();
+                    continue;
+                }
+
+                // the money waits in the escrow until the bid is filled
+                let transaction = ContainerTransaction::new(entity).with_move(
+                    money,
+                    cost,
+                    inventory.container,
+                    book.escrow,
+                );
+                let order = Order::new(
+                    action.actor(),
+                    Side::Bid,
+                    item,
+                    BUY_AMOUNT,
+                    price,
+                    time.elapsed_seconds() + ORDER_LIFETIME,
+                )
+                .with_escrow(cost);
+                transactions.send(transaction);
+                pending.insert(
+                    entity,
+                    PendingOrders {
+                        market,
+                        orders: vec![order],
+                    },
+                );
+            }
+        }
+
+        if action.is_cancelled() {
+            debug!("Buying was interrupted.");
+            action.failure();
+        }
+    }
+}
+
+/// Wants to buy food when hungry and remedies when tired, if the nearest market has any
+/// and there is money for it.
+#[derive(Component, Clone, Default, ScorerSpawn)]
+pub struct BuyNeedScorer;
+
+pub fn buy_need_scorer(
+    actors: Query<ShopperQuery>,
+    mut query: Query<ScorerQuery, With<BuyNeedScorer>>,
+
+    markets: Query<(Entity, &Transform, &OrderBook), With<Market>>,
+    items: Res<ItemDatabase>,
+    assets: Res<Assets<ItemAsset>>,
+    carried: ReadContainer<Entity>,
+    wallet: ReadConsumable,
+) {
+    let Some(money) = items.get("money") else {
+        return;
+    };
+
+    for mut score in &mut query {
+        let (inventory, transform, ctrl, hunger, fatigue) =
+            actors.get(score.actor()).expect("actor");
+
+        let carries = |filter: ItemFilter| {
+            carried
+                .find_recursive(filter, inventory.container, |_| ())
+                .is_some()
+        };
+        // one bid at a time, what it buys is on its way
+        let affordable = shopping_need(hunger, fatigue, carries)
+            .zip(nearest_market(
+                markets
+                    .iter()
+                    .map(|(market, transform, _)| (market, transform)),
+                transform.translation,
+            ))
+            .and_then(|(need, market)| {
+                let (_, _, book) = markets.get(market).ok()?;
+                if book.has_open(score.actor(), Side::Bid) {
+                    return None;
+                }
+                cheapest_offer(need, book, &assets)
+            })
+            .is_some_and(|(_, price)| {
+                wallet.sum(money.into(), inventory.container) >= price * BUY_AMOUNT
+            });
+
+        score.set(if affordable && !ctrl.is_sleeping {
+            0.8
+        } else {
+            0.0
+        });
     }
 }
@@ -0,0 +1,553 @@
+use super::{Market, MarketGoods, MarketStock, PricingConfig, StockRules};
+use crate::{
+    character::Inventory,
+    loading::ItemDatabase,
+    mechanics::{
+        item::{
+            BaseValue, ContainerIndex, ContainerTransaction, ItemAsset, ReadCapacity,
+            TransactionResult,
+        },
+        treasury::{TaxConfig, Treasury, TreasuryLedger},
+    },
+};
+use bevy::{prelude::*, utils::HashMap};
+
+/// Seconds of game time an order stays in the book.
+pub const ORDER_LIFETIME: f32 = 30.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    /// Wants to buy.
+    Bid,
+    /// Wants to sell.
+    Ask,
+}
+
+/// An offer to buy or sell at a [`Market`].
+#[derive(Clone, Debug)]
+pub struct Order {
+    id: u64,
+    /// The character trading, or the market itself.
+    pub trader: Entity,
+    pub side: Side,
+    pub item: Handle<ItemAsset>,
+    pub quantity: f32,
+    /// Most paid or least taken per unit.
+    pub price: f32,
+    /// Game time the order leaves the book at.
+    pub expires: f32,
+    /// Goods of an ask or money of a bid still in the escrow for this order.
+    escrowed: f32,
+}
+
+impl Order {
+    pub fn new(
+        trader: Entity,
+        side: Side,
+        item: &Handle<ItemAsset>,
+        quantity: f32,
+        price: f32,
+        expires: f32,
+    ) -> Self {
+        Self {
+            id: 0,
+            trader,
+            side,
+            item: item.clone(),
+            quantity,
+            price,
+            expires,
+            escrowed: 0.0,
+        }
+    }
+
+    /// The order set aside `amount` in the escrow, which is returned when it expires.
+    pub fn with_escrow(mut self, amount: f32) -> Self {
+        self.escrowed = amount;
+        self
+    }
+
+    /// Earlier orders have lower ids.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn escrowed(&self) -> f32 {
+        self.escrowed
+    }
+}
+
+/// A matched bid and ask waiting for their transaction.
+#[derive(Clone, Debug)]
+struct Settlement {
+    bid: u64,
+    ask: u64,
+    buyer: Entity,
+    seller: Entity,
+    item: AssetId<ItemAsset>,
+    quantity: f32,
+    price: f32,
+    /// Unit price the bid set aside in the escrow.
+    bid_price: f32,
+    /// Sales tax going to the treasury.
+    tax: f32,
+}
+
+/// Open orders at a [`Market`].
+///
+/// Goods of asks and money of bids wait in the escrow container until they are filled,
+/// the market quotes its own orders from its stock and pays from or into its till,
+/// the money in its [`MarketStock`].
+#[derive(Component, Debug)]
+pub struct OrderBook {
+    pub escrow: Entity,
+    orders: Vec<Order>,
+    next_id: u64,
+    settling: Option<Settlement>,
+    last_prices: HashMap<AssetId<ItemAsset>, f32>,
+}
+
+impl OrderBook {
+    pub fn new(escrow: Entity) -> Self {
+        Self {
+            escrow,
+            orders: Vec::new(),
+            next_id: 0,
+            settling: None,
+            last_prices: HashMap::new(),
+        }
+    }
+
+    pub fn place(&mut self, mut order: Order) {
+        order.id = self.next_id;
+        self.next_id += 1;
+        self.orders.push(order);
+    }
+
+    pub fn orders(&self) -> impl Iterator<Item = &Order> {
+        self.orders.iter()
+    }
+
+    /// The highest bid or the lowest ask for the item, the earlier one on a tie.
+    pub fn best(&self, side: Side, item: impl Into<AssetId<ItemAsset>>) -> Option<&Order> {
+        let item = item.into();
+        let orders = self
+            .orders
+            .iter()
+            .filter(|order| order.side == side && order.item.id() == item);
+
+        match side {
+            Side::Bid => orders.min_by(|a, b| f32::total_cmp(&b.price, &a.price)),
+            Side::Ask => orders.min_by(|a, b| f32::total_cmp(&a.price, &b.price)),
+        }
+    }
+
+    pub fn has_open(&self, trader: Entity, side: Side) -> bool {
+        self.orders
+            .iter()
+            .any(|order| order.trader == trader && order.side == side)
+    }
+
+    /// Unit price of the last trade of the item.
+    pub fn last_price(&self, item: impl Into<AssetId<ItemAsset>>) -> Option<f32> {
+        self.last_prices.get(&item.into()).copied()
+    }
+
+    /// Keeps the market's own order for the item at `quantity` and `price`.
+    ///
+    /// The order is changed in place so a trade being settled still finds it,
+    /// it is only placed or taken out when the quantity becomes non-zero or zero.
+    fn quote(
+        &mut self,
+        market: Entity,
+        side: Side,
+        item: &Handle<ItemAsset>,
+        quantity: f32,
+        price: f32,
+    ) {
+        let quoted = |order: &Order| {
+            order.trader == market && order.side == side && order.item.id() == item.id()
+        };
+
+        if quantity <= 0.0 {
+            self.orders.retain(|order| !quoted(order));
+        } else if let Some(order) = self.orders.iter_mut().find(|order| quoted(order)) {
+            order.quantity = quantity;
+            order.price = price;
+        } else {
+            let order = Order::new(market, side, item, quantity, price, f32::INFINITY);
+            self.place(order);
+        }
+    }
+
+    fn get_mut(&mut self, id: u64) -> Option<&mut Order> {
+        self.orders.iter_mut().find(|order| order.id == id)
+    }
+
+    /// The first bid and ask which cross, skipping items for which `skip` says so.
+    fn crossing(&self, skip: impl Fn(&Order, &Order) -> bool) -> Option<(&Order, &Order)> {
+        self.orders
+            .iter()
+            .filter(|order| order.side == Side::Bid)
+            .filter_map(|order| {
+                let bid = self.best(Side::Bid, &order.item)?;
+                let ask = self.best(Side::Ask, &order.item)?;
+                let crosses = bid.price >= ask.price && bid.trader != ask.trader;
+                (crosses && !skip(bid, ask)).then_some((bid, ask))
+            })
+            .next()
+    }
+}
+
+/// The market bids for what it has room for and money to pay for, and asks for what
+/// it has in stock, at the prices of its [`PricingConfig`].
+pub fn quote_market_orders(
+    config: Res<PricingConfig>,
+    rules: Res<StockRules>,
+    items: Res<ItemDatabase>,
+    assets: Res<Assets<ItemAsset>>,
+    mut markets: Query<(Entity, &MarketStock, &MarketGoods, &mut OrderBook), With<Market>>,
+    indices: Query<&ContainerIndex>,
+) {
+    let Some(money) = items.get("money") else {
+        return;
+    };
+
+    for (market, stock, goods, mut book) in &mut markets {
+        let balance = indices
+            .get(stock.container)
+            .map_or(0.0, |index| index.total(money));
+
+        for (_, item) in items.iter() {
+            let Some(BaseValue(base)) = assets.get(item).and_then(|asset| asset.get()) else {
+                continue;
+            };
+
+            let stock = goods.get(item);
+            let price = config.sell_price(&stock, base);
+            let room = rules.limit(&items, item) - stock.stock;
+            let affordable = if price > 0.0 {
+                balance / price
+            } else {
+                f32::INFINITY
+            };
+            book.quote(market, Side::Bid, item, room.min(affordable), price);
+
+            let price = config.buy_price(&stock, base);
+            book.quote(market, Side::Ask, item, stock.stock, price);
+        }
+    }
+}
+
+/// Settles one crossing bid and ask per market and tick, at the price of the earlier order.
+///
+/// The seller pays the sales tax of [`TaxConfig`] out of the price, when there is a [`Treasury`].
+#[allow(clippy::too_many_arguments)]
+pub fn match_orders(
+    mut markets: Query<(Entity, &MarketStock, &mut MarketGoods, &mut OrderBook), With<Market>>,
+    inventories: Query<&Inventory>,
+    capacity: ReadCapacity,
+    items: Res<ItemDatabase>,
+    taxes: Res<TaxConfig>,
+    treasury: Query<&Treasury>,
+    mut ledger: ResMut<TreasuryLedger>,
+    mut transactions: EventWriter<ContainerTransaction>,
+    mut results: EventReader<TransactionResult>,
+) {
+    let Some(money) = items.get("money") else {
+        return;
+    };
+
+    let treasury = treasury
+        .get_single()
+        .ok()
+        .map(|treasury| treasury.container);
+
+    let mut results: HashMap<Entity, _> = results
+        .read()
+        .map(|event| (event.requester, event.result.clone()))
+        .collect();
+
+    for (market, stock, mut goods, mut book) in &mut markets {
+        if let Some(result) = results.remove(&book.escrow) {
+            let Some(settled) = book.settling.take() else {
+                continue;
+            };
+
+            match result {
+                Ok(()) => {
+                    debug!(
+                        "Traded {} units at {} between {:?} and {:?}",
+                        settled.quantity, settled.price, settled.buyer, settled.seller
+                    );
+                    for (id, escrowed) in [
+                        (settled.bid, settled.quantity * settled.bid_price),
+                        (settled.ask, settled.quantity),
+                    ] {
+                        if let Some(order) = book.get_mut(id) {
+                            order.quantity -= settled.quantity;
+                            // the market's own orders hold nothing in the escrow
+                            if order.trader != market {
+                                order.escrowed = (order.escrowed - escrowed).max(0.0);
+                            }
+                        }
+                    }
+                    book.orders.retain(|order| order.quantity > 0.0);
+                    book.last_prices.insert(settled.item, settled.price);
+                    ledger.record_sales(settled.tax);
+
+                    if settled.buyer == market {
+                        goods.record_sale(settled.item, settled.quantity);
+                    }
+                    if settled.seller == market {
+                        goods.record_purchase(settled.item, settled.quantity);
+                    }
+                }
+                Err(err) => {
+                    // the same trade would fail again, so the traders get their orders back,
+                    // the market keeps quoting its own
+                    warn!("Settling a trade at {:?} failed: {}", market, err);
+                    for id in [settled.bid, settled.ask] {
+                        if let Some(order) = book.get_mut(id).filter(|order| order.trader != market)
+                        {
+                            order.expires = 0.0;
+                        }
+                    }
+                }
+            }
+        }
+
+        if book.settling.is_some() {
+            continue;
+        }
+
+        // buyers take no more than they have room for
+        let room = |order: &Order| match order.trader {
+            trader if trader == market => order.quantity,
+            trader => inventories.get(trader).map_or(0.0, |inventory| {
+                let room = capacity.room_for(inventory.container, &order.item, true);
+                order.quantity.min(room)
+            }),
+        };
+        let Some((bid, ask)) = book
+            .crossing(|bid, _| room(bid) <= 0.0)
+            .map(|(bid, ask)| (bid.clone(), ask.clone()))
+        else {
+            continue;
+        };
+
+        let quantity = room(&bid).min(ask.quantity);
+        let price = if bid.id < ask.id {
+            bid.price
+        } else {
+            ask.price
+        };
+        let paid = quantity * price;
+        let tax = treasury.map_or(0.0, |_| paid * taxes.sales_tax.clamp(0.0, 1.0));
+        let (buyer, seller) = (bid.trader, ask.trader);
+        let inventory = |trader| inventories.get(trader).map(|inventory| inventory.container);
+
+        let mut transaction = ContainerTransaction::new(book.escrow);
+
+        // goods from the shelves or the escrow, to the shelves or the buyer
+        let from = if seller == market {
+            stock.container
+        } else {
+            book.escrow
+        };
+        let to = if buyer == market {
+            stock.container
+        } else {
+            inventory(buyer).unwrap()
+        };
+        transaction = transaction.with_move(&ask.item, quantity, from, to);
+
+        // money from the escrow or the till, to the seller or the till and the treasury
+        if buyer == market {
+            let Ok(seller) = inventory(seller) else {
+                book.get_mut(ask.id).unwrap().expires = 0.0;
+                continue;
+            };
+            transaction = transaction.with_move(money, paid - tax, stock.container, seller);
+            if let Some(treasury) = treasury.filter(|_| tax > 0.0) {
+                transaction = transaction.with_move(money, tax, stock.container, treasury);
+            }
+        } else {
+            if let Some(treasury) = treasury.filter(|_| tax > 0.0) {
+                transaction = transaction.with_move(money, tax, book.escrow, treasury);
+            }
+            if seller == market {
+                let till = stock.container;
+                transaction = transaction.with_move(money, paid - tax, book.escrow, till);
+            } else if let Ok(seller) = inventory(seller) {
+                transaction = transaction.with_move(money, paid - tax, book.escrow, seller);
+            } else {
+                book.get_mut(ask.id).unwrap().expires = 0.0;
+                continue;
+            }
+
+            // the bid set aside its own price, trading cheaper returns the rest
+            let change = quantity * bid.price - paid;
+            if change > 0.0 {
+                transaction = transaction.with_move(money, change, book.escrow, to);
+            }
+        }
+
+        book.settling = Some(Settlement {
+            bid: bid.id,
+            ask: ask.id,
+            buyer,
+            seller,
+            item: ask.item.id(),
+            quantity,
+            price,
+            bid_price: bid.price,
+            tax,
+        });
+        transactions.send(transaction);
+    }
+}
+
+/// Takes expired orders out of the book and returns what they set aside.
+///
+/// The market's own orders are left alone, they take nothing from the escrow.
+pub fn expire_orders(
+    time: Res<Time<Virtual>>,
+    mut markets: Query<(Entity, &mut OrderBook), With<Market>>,
+    inventories: Query<&Inventory>,
+    items: Res<ItemDatabase>,
+    mut transactions: EventWriter<ContainerTransaction>,
+) {
+    let Some(money) = items.get("money") else {
+        return;
+    };
+
+    for (market, mut book) in &mut markets {
+        // a trade being settled may still take from the escrow
+        if book.settling.is_some() {
+            continue;
+        }
+
+        let now = time.elapsed_seconds();
+        let (expired, open) = book
+            .orders
+            .drain(..)
+            .partition(|order| order.trader != market && order.expires <= now);
+        book.orders = open;
+
+        for order in expired {
+            let item = match order.side {
+                Side::Ask => &order.item,
+                Side::Bid => money,
+            };
+            debug!("Order {} of {:?} expired", order.id, order.trader);
+            let amount = order.escrowed;
+            if amount <= 0.0 {
+                continue;
+            }
+
+            let transaction = match inventories.get(order.trader) {
+                Ok(inventory) => ContainerTransaction::new(order.trader).with_move(
+                    item,
+                    amount,
+                    book.escrow,
+                    inventory.container,
+                ),
+                Err(_) => {
+                    ContainerTransaction::new(order.trader).with_destroy(item, amount, book.escrow)
+                }
+            };
+            transactions.send(transaction);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ITEM: Handle<ItemAsset> = Handle::weak_from_u128(1);
+    const OTHER: Handle<ItemAsset> = Handle::weak_from_u128(2);
+
+    fn order(trader: u32, side: Side, item: &Handle<ItemAsset>, price: f32) -> Order {
+        let trader = Entity::from_raw(trader);
+        Order::new(trader, side, item, 1.0, price, f32::INFINITY)
+    }
+
+    fn book(orders: impl IntoIterator<Item = Order>) -> OrderBook {
+        let mut book = OrderBook::new(Entity::PLACEHOLDER);
+        for order in orders {
+            book.place(order);
+        }
+        book
+    }
+
+    #[test]
+    fn best_takes_the_highest_bid_and_the_lowest_ask() {
+        let book = book([
+            order(1, Side::Bid, &ITEM, 2.0),
+            order(2, Side::Bid, &ITEM, 3.0),
+            order(3, Side::Bid, &OTHER, 9.0),
+            order(4, Side::Ask, &ITEM, 5.0),
+            order(5, Side::Ask, &ITEM, 4.0),
+        ]);
+
+        assert_eq!(book.best(Side::Bid, &ITEM).unwrap().price, 3.0);
+        assert_eq!(book.best(Side::Ask, &ITEM).unwrap().price, 4.0);
+        assert!(book.best(Side::Ask, &OTHER).is_none());
+    }
+
+    #[test]
+    fn best_prefers_the_earlier_order_on_a_tie() {
+        let book = book([
+            order(1, Side::Ask, &ITEM, 4.0),
+            order(2, Side::Ask, &ITEM, 4.0),
+        ]);
+
+        assert_eq!(book.best(Side::Ask, &ITEM).unwrap().id(), 0);
+    }
+
+    #[test]
+    fn crossing_needs_a_bid_at_or_above_the_ask() {
+        let apart = book([
+            order(1, Side::Bid, &ITEM, 3.0),
+            order(2, Side::Ask, &ITEM, 4.0),
+        ]);
+        assert!(apart.crossing(|_, _| false).is_none());
+
+        let crossed = book([
+            order(1, Side::Bid, &ITEM, 4.0),
+            order(2, Side::Ask, &ITEM, 4.0),
+        ]);
+        let (bid, ask) = crossed.crossing(|_, _| false).unwrap();
+        assert_eq!((bid.id(), ask.id()), (0, 1));
+        assert!(crossed.crossing(|_, _| true).is_none());
+    }
+
+    #[test]
+    fn crossing_skips_orders_of_the_same_trader() {
+        let book = book([
+            order(1, Side::Bid, &ITEM, 5.0),
+            order(1, Side::Ask, &ITEM, 4.0),
+        ]);
+
+        assert!(book.crossing(|_, _| false).is_none());
+    }
+
+    #[test]
+    fn quotes_change_the_market_order_in_place() {
+        let market = Entity::from_raw(1);
+        let mut book = book([order(2, Side::Bid, &ITEM, 3.0)]);
+
+        book.quote(market, Side::Ask, &ITEM, 5.0, 4.0);
+        let id = book.best(Side::Ask, &ITEM).unwrap().id();
+        book.quote(market, Side::Ask, &ITEM, 2.0, 4.5);
+
+        let ask = book.best(Side::Ask, &ITEM).unwrap();
+        assert_eq!((ask.id(), ask.quantity, ask.price), (id, 2.0, 4.5));
+
+        book.quote(market, Side::Ask, &ITEM, 0.0, 4.5);
+        assert!(book.best(Side::Ask, &ITEM).is_none());
+        assert_eq!(book.orders().count(), 1);
+    }
+}
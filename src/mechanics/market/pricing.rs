@@ -0,0 +1,255 @@
+use super::Market;
+use crate::mechanics::item::{BaseValue, ContainerIndex, ItemAsset};
+use bevy::{prelude::*, utils::HashMap};
+use std::collections::VecDeque;
+
+/// How often [`PriceHistory`] takes a sample, in seconds of game time.
+pub const PRICE_SAMPLE_INTERVAL: f32 = 1.0;
+/// How many samples [`PriceHistory`] keeps per item.
+pub const PRICE_HISTORY_LENGTH: usize = 120;
+
+/// How prices follow supply and demand, shared by all markets.
+#[derive(Resource, Clone, Debug)]
+pub struct PricingConfig {
+    /// How strongly prices react to supply and demand, `0.0` keeps them at the base value.
+    pub elasticity: f32,
+    /// Amount of an item a market is happy to hold, more of it pushes the price down.
+    pub reference_stock: f32,
+    /// Part of the recent trade volume forgotten every second.
+    pub volume_decay: f32,
+    /// How much more a market asks than it pays for the same item.
+    pub spread: f32,
+    /// Prices never leave `[base / max_ratio, base * max_ratio]`.
+    pub max_ratio: f32,
+}
+
+impl Default for PricingConfig {
+    fn default() -> Self {
+        Self {
+            elasticity: 0.8,
+            reference_stock: 100.0,
+            volume_decay: 0.05,
+            spread: 0.2,
+            max_ratio: 4.0,
+        }
+    }
+}
+
+impl PricingConfig {
+    /// Price relative to the base value, above `1.0` when demand outgrows supply.
+    pub fn ratio(&self, goods: &Goods) -> f32 {
+        let supply = self.reference_stock + goods.stock + goods.sold;
+        let demand = self.reference_stock + goods.bought;
+        if supply <= 0.0 {
+            return self.max_ratio;
+        }
+
+        (demand / supply)
+            .powf(self.elasticity)
+            .clamp(self.max_ratio.recip(), self.max_ratio)
+    }
+
+    /// What the market pays for a unit worth `base`.
+    pub fn sell_price(&self, goods: &Goods, base: f32) -> f32 {
+        base * self.ratio(goods)
+    }
+
+    /// What the market asks for a unit worth `base`.
+    pub fn buy_price(&self, goods: &Goods, base: f32) -> f32 {
+        self.sell_price(goods, base) * (1.0 + self.spread)
+    }
+}
+
+/// Supply and demand of a single item at a market.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Goods {
+    /// Units on the shelves of the market.
+    pub stock: f32,
+    /// Recent units sold to the market.
+    pub sold: f32,
+    /// Recent units bought from the market.
+    pub bought: f32,
+}
+
+/// Stock and recent trade volume of a [`Market`].
+#[derive(Component, Clone, Debug, Default)]
+pub struct MarketGoods {
+    goods: HashMap<AssetId<ItemAsset>, Goods>,
+}
+
+impl MarketGoods {
+    pub fn get(&self, item: impl Into<AssetId<ItemAsset>>) -> Goods {
+        self.goods.get(&item.into()).copied().unwrap_or_default()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (AssetId<ItemAsset>, &Goods)> {
+        self.goods.iter().map(|(id, goods)| (*id, goods))
+    }
+
+    /// Someone sold `amount` of the item to the market.
+    pub fn record_sale(&mut self, item: impl Into<AssetId<ItemAsset>>, amount: f32) {
+        self.goods.entry(item.into()).or_default().sold += amount;
+    }
+
+    /// Someone bought `amount` of the item from the market.
+    pub fn record_purchase(&mut self, item: impl Into<AssetId<ItemAsset>>, amount: f32) {
+        self.goods.entry(item.into()).or_default().bought += amount;
+    }
+
+    /// Takes the stock from the index of the market container.
+    pub fn count_stock(&mut self, index: &ContainerIndex) {
+        for goods in self.goods.values_mut() {
+            goods.stock = 0.0;
+        }
+        for item in index.assets() {
+            self.goods.entry(item).or_default().stock = index.total(item);
+        }
+    }
+}
+
+pub fn decay_trade_volume(
+    time: Res<Time<Virtual>>,
+    config: Res<PricingConfig>,
+    mut markets: Query<&mut MarketGoods>,
+) {
+    let keep = (1.0 - config.volume_decay)
+        .max(0.0)
+        .powf(time.delta_seconds());
+    for mut market in &mut markets {
+        for goods in market.goods.values_mut() {
+            goods.sold *= keep;
+            goods.bought *= keep;
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PriceSample {
+    /// Seconds of game time.
+    pub time: f32,
+    pub sell: f32,
+    pub buy: f32,
+}
+
+/// Recent prices of every traded item at every market, oldest first.
+#[derive(Resource, Default, Debug)]
+pub struct PriceHistory {
+    samples: HashMap<(Entity, AssetId<ItemAsset>), VecDeque<PriceSample>>,
+    since_last: f32,
+}
+
+impl PriceHistory {
+    pub fn get(
+        &self,
+        market: Entity,
+        item: impl Into<AssetId<ItemAsset>>,
+    ) -> impl Iterator<Item = &PriceSample> {
+        self.samples
+            .get(&(market, item.into()))
+            .into_iter()
+            .flatten()
+    }
+
+    /// Items with any history at the market.
+    pub fn items(&self, market: Entity) -> impl Iterator<Item = AssetId<ItemAsset>> + '_ {
+        self.samples
+            .keys()
+            .filter(move |(entity, _)| *entity == market)
+            .map(|(_, item)| *item)
+    }
+
+    fn push(&mut self, market: Entity, item: AssetId<ItemAsset>, sample: PriceSample) {
+        let samples = self.samples.entry((market, item)).or_default();
+        if samples.len() >= PRICE_HISTORY_LENGTH {
+            samples.pop_front();
+        }
+        samples.push_back(sample);
+    }
+}
+
+pub fn record_prices(
+    time: Res<Time<Virtual>>,
+    config: Res<PricingConfig>,
+    assets: Res<Assets<ItemAsset>>,
+    markets: Query<(Entity, &MarketGoods), With<Market>>,
+    mut history: ResMut<PriceHistory>,
+) {
+    history.since_last += time.delta_seconds();
+    if history.since_last < PRICE_SAMPLE_INTERVAL {
+        return;
+    }
+    history.since_last = 0.0;
+
+    // markets that are gone leave no history behind
+    history
+        .samples
+        .retain(|(market, _), _| markets.contains(*market));
+
+    for (market, goods) in &markets {
+        for (item, goods) in goods.iter() {
+            let Some(BaseValue(base)) = assets.get(item).and_then(|asset| asset.get()) else {
+                continue;
+            };
+
+            let sample = PriceSample {
+                time: time.elapsed_seconds(),
+                sell: config.sell_price(goods, base),
+                buy: config.buy_price(goods, base),
+            };
+            history.push(market, item, sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn goods(stock: f32, sold: f32, bought: f32) -> Goods {
+        Goods {
+            stock,
+            sold,
+            bought,
+        }
+    }
+
+    #[test]
+    fn ratio_follows_supply_and_demand() {
+        let config = PricingConfig::default();
+
+        assert_eq!(config.ratio(&Goods::default()), 1.0);
+        assert!(config.ratio(&goods(50.0, 0.0, 0.0)) < 1.0);
+        assert!(config.ratio(&goods(0.0, 0.0, 50.0)) > 1.0);
+        // selling to the market adds to its supply like stock does
+        assert_eq!(
+            config.ratio(&goods(0.0, 50.0, 0.0)),
+            config.ratio(&goods(50.0, 0.0, 0.0))
+        );
+    }
+
+    #[test]
+    fn ratio_is_clamped() {
+        let config = PricingConfig {
+            elasticity: 1.0,
+            ..default()
+        };
+
+        assert_eq!(config.ratio(&goods(1000.0, 0.0, 0.0)), 0.25);
+        assert_eq!(config.ratio(&goods(0.0, 0.0, 1000.0)), 4.0);
+        // no supply at all, the market pays what it can
+        let empty = PricingConfig {
+            reference_stock: 0.0,
+            ..config
+        };
+        assert_eq!(empty.ratio(&Goods::default()), 4.0);
+    }
+
+    #[test]
+    fn buy_price_adds_the_spread() {
+        let config = PricingConfig::default();
+        let goods = Goods::default();
+
+        assert_eq!(config.sell_price(&goods, 10.0), 10.0);
+        assert_eq!(config.buy_price(&goods, 10.0), 12.0);
+    }
+}
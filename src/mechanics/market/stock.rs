@@ -0,0 +1,159 @@
+use super::{Market, MarketGoods};
+use crate::{
+    loading::ItemDatabase,
+    mechanics::item::{ContainerCapacity, ContainerIndex, ContainerTransaction, ItemAsset},
+};
+use bevy::prelude::*;
+
+/// Room on the shelves of a market.
+pub const MARKET_CAPACITY: ContainerCapacity = ContainerCapacity {
+    slots: 16,
+    weight: 400.0,
+    volume: f32::INFINITY,
+};
+
+/// Money a market starts with in its stock, sellers are paid from it.
+pub const MARKET_FUNDS: f32 = 500.0;
+
+/// Where a [`Market`] keeps the goods it bought and has for sale, and its money.
+#[derive(Component, Reflect)]
+pub struct MarketStock {
+    pub container: Entity,
+}
+
+/// How a market handles a single item.
+#[derive(Clone, Debug)]
+pub struct StockRule {
+    /// Item id in the [`ItemDatabase`].
+    pub item: String,
+    /// Units the market tops up to on its own, `0.0` for items it only gets from sellers.
+    pub restock_to: f32,
+    /// Most units delivered at once.
+    pub restock_amount: f32,
+    /// Units past which the market buys no more of the item.
+    pub limit: f32,
+}
+
+impl StockRule {
+    pub fn new(item: impl Into<String>, limit: f32) -> Self {
+        Self {
+            item: item.into(),
+            restock_to: 0.0,
+            restock_amount: 0.0,
+            limit,
+        }
+    }
+
+    pub fn with_restock(mut self, restock_to: f32, restock_amount: f32) -> Self {
+        self.restock_to = restock_to;
+        self.restock_amount = restock_amount;
+        self
+    }
+}
+
+/// Stock limits and restocking of all markets.
+#[derive(Resource, Clone, Debug)]
+pub struct StockRules {
+    pub rules: Vec<StockRule>,
+    /// Item ids thrown out with every delivery, like spoiled food.
+    pub discard: Vec<String>,
+    /// Limit of items without a rule.
+    pub default_limit: f32,
+    /// Seconds between deliveries.
+    pub restock_interval: f32,
+}
+
+impl Default for StockRules {
+    fn default() -> Self {
+        Self {
+            rules: vec![
+                StockRule::new("raw_food", 200.0),
+                StockRule::new("bread", 40.0).with_restock(20.0, 5.0),
+                StockRule::new("potion", 20.0).with_restock(10.0, 2.0),
+            ],
+            discard: vec!["rotten_food".to_string()],
+            default_limit: 40.0,
+            restock_interval: 10.0,
+        }
+    }
+}
+
+impl StockRules {
+    pub fn get(&self, items: &ItemDatabase, item: &Handle<ItemAsset>) -> Option<&StockRule> {
+        let id = items.id_of(item)?;
+        self.rules.iter().find(|rule| rule.item == id)
+    }
+
+    /// Most units of the item a market holds.
+    pub fn limit(&self, items: &ItemDatabase, item: &Handle<ItemAsset>) -> f32 {
+        self.get(items, item)
+            .map_or(self.default_limit, |rule| rule.limit)
+    }
+}
+
+/// Keeps [`MarketGoods`] stock in line with the market container.
+pub fn count_market_stock(
+    mut markets: Query<(&MarketStock, &mut MarketGoods), With<Market>>,
+    indices: Query<&ContainerIndex, Changed<ContainerIndex>>,
+) {
+    for (stock, mut goods) in &mut markets {
+        if let Ok(index) = indices.get(stock.container) {
+            goods.count_stock(index);
+        }
+    }
+}
+
+/// Suppliers deliver items with a restock rule every [`StockRules::restock_interval`]
+/// and take away the discarded ones.
+pub fn restock_markets(
+    time: Res<Time<Virtual>>,
+    rules: Res<StockRules>,
+    items: Res<ItemDatabase>,
+    markets: Query<(Entity, &MarketStock), With<Market>>,
+    indices: Query<&ContainerIndex>,
+    mut next_delivery: Local<f32>,
+    mut transactions: EventWriter<ContainerTransaction>,
+) {
+    if time.elapsed_seconds() < *next_delivery {
+        return;
+    }
+    *next_delivery = time.elapsed_seconds() + rules.restock_interval;
+
+    for (market, stock) in &markets {
+        let Ok(index) = indices.get(stock.container) else {
+            continue;
+        };
+
+        for rule in &rules.rules {
+            let Some(item) = items.get(&rule.item) else {
+                continue;
+            };
+
+            let missing = (rule.restock_to - index.total(item)).min(rule.restock_amount);
+            if missing > 0.0 {
+                debug!("Delivering {} {} to {:?}", missing, rule.item, market);
+                transactions.send(ContainerTransaction::new(market).with_create(
+                    item,
+                    missing,
+                    stock.container,
+                ));
+            }
+        }
+
+        for id in &rules.discard {
+            let Some(item) = items.get(id) else {
+                continue;
+            };
+
+            let amount = index.total(item);
+            if amount > 0.0 {
+                debug!("Discarding {} {} from {:?}", amount, id, market);
+                transactions.send(ContainerTransaction::new(market).with_destroy(
+                    item,
+                    amount,
+                    stock.container,
+                ));
+            }
+        }
+    }
+}
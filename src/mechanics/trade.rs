@@ -0,0 +1,292 @@
+use super::item::{
+    BaseValue, Consumable, ContainerTransaction, ItemAsset, ItemFilter, ReadContainer,
+    TransactionResult,
+};
+use super::market::{in_need, shopping_need};
+use super::{Fatigue, Hunger};
+use crate::{
+    character::{CharacterController, Inventory},
+    game_state::GameState,
+};
+use bevy::{
+    ecs::system::{lifetimeless::Read, SystemParam},
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use big_brain::prelude::*;
+
+/// How close two characters have to be to trade.
+pub const TRADE_RANGE: f32 = 2.0;
+/// Seconds a character waits for the other side to start trading.
+pub const TRADE_PATIENCE: f32 = 3.0;
+/// Units of the needed item changing hands in a trade.
+pub const TRADE_AMOUNT: f32 = 1.0;
+
+pub struct TradePlugin;
+
+impl Plugin for TradePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PreUpdate,
+            (
+                trade_action.in_set(BigBrainSet::Actions),
+                trade_scorer.in_set(BigBrainSet::Scorers),
+            )
+                .run_if(in_state(GameState::Playing)),
+        );
+    }
+}
+
+/// Goods two characters swap, the taker gets what they need and pays the giver for it.
+#[derive(Clone, Debug)]
+pub struct Deal {
+    pub taker: Entity,
+    pub giver: Entity,
+    pub wanted: (Handle<ItemAsset>, f32),
+    pub payment: (Handle<ItemAsset>, f32),
+    from: Entity,
+    to: Entity,
+}
+
+impl Deal {
+    /// Both sides of the deal in one transaction, they happen together or not at all.
+    pub fn transaction(&self, requester: Entity) -> ContainerTransaction {
+        let (wanted, amount) = &self.wanted;
+        let (payment, price) = &self.payment;
+        ContainerTransaction::new(requester)
+            .with_move(wanted, *amount, self.from, self.to)
+            .with_move(payment, *price, self.to, self.from)
+    }
+}
+
+pub type TraderQuery = (
+    Entity,
+    Read<Inventory>,
+    Read<Transform>,
+    Read<CharacterController>,
+    Option<Read<Hunger>>,
+    Option<Read<Fatigue>>,
+);
+
+pub type TradeGoodsQuery = (Read<Consumable>, Option<Read<BaseValue>>);
+
+/// Finds deals between characters from what they need and carry.
+#[derive(SystemParam)]
+pub struct Deals<'w, 's> {
+    traders: Query<'w, 's, TraderQuery>,
+    goods: ReadContainer<'w, 's, TradeGoodsQuery>,
+}
+
+impl Deals<'_, '_> {
+    /// What `taker` would get from `giver` and pay for it.
+    ///
+    /// The giver hands over nothing it needs itself and takes either something it needs
+    /// or food worth at least as much in return.
+    ///
+    /// Needs count what is carried in bags too, like buying does, but only stacks at the top
+    /// of an inventory are traded, since the deal moves goods between the two inventories.
+    pub fn between(&self, taker: Entity, giver: Entity) -> Option<Deal> {
+        let (_, taker_inventory, _, _, taker_hunger, taker_fatigue) =
+            self.traders.get(taker).ok()?;
+        let (_, giver_inventory, _, _, giver_hunger, giver_fatigue) =
+            self.traders.get(giver).ok()?;
+        let (from, to) = (giver_inventory.container, taker_inventory.container);
+        let carries = |container| {
+            move |filter: ItemFilter| {
+                self.goods
+                    .find_recursive(filter, container, |_| ())
+                    .is_some()
+            }
+        };
+
+        let need = shopping_need(taker_hunger, taker_fatigue, carries(to))?;
+        if in_need(giver_hunger, giver_fatigue, need) {
+            return None;
+        }
+
+        // not `iter_recursive`, the deal can not take from the giver's bags
+        let (wanted, value) = self
+            .goods
+            .iter(ItemFilter::Component(need), from)
+            .find(|(_, (stack, _))| stack.current >= TRADE_AMOUNT)
+            .map(|(item, (_, value))| (item, value.map_or(0.0, |BaseValue(value)| *value)))?;
+
+        // something the giver needs, which the taker can spare
+        let swap = shopping_need(giver_hunger, giver_fatigue, carries(from))
+            .filter(|&want| !in_need(taker_hunger, taker_fatigue, want))
+            .and_then(|want| {
+                self.goods
+                    .iter(ItemFilter::Component(want), to)
+                    .find(|(_, (stack, _))| stack.current >= TRADE_AMOUNT)
+                    .map(|(item, _)| (item.clone(), TRADE_AMOUNT))
+            });
+
+        // or enough food to make up for the value
+        let payment = swap.or_else(|| {
+            self.goods
+                .iter(ItemFilter::Tag("food"), to)
+                .filter(|(item, _)| *item != wanted)
+                .find_map(|(item, (stack, base))| {
+                    let BaseValue(base) = base.filter(|BaseValue(base)| *base > 0.0)?;
+                    let units = (value * TRADE_AMOUNT / base).ceil().max(1.0);
+                    (stack.current >= units).then(|| (item.clone(), units))
+                })
+        })?;
+
+        Some(Deal {
+            taker,
+            giver,
+            wanted: (wanted.clone(), TRADE_AMOUNT),
+            payment,
+            from,
+            to,
+        })
+    }
+
+    /// The closest awake character in range with a deal either way.
+    pub fn nearby(&self, trader: Entity) -> Option<(Entity, Deal)> {
+        let (_, _, transform, ..) = self.traders.get(trader).ok()?;
+
+        self.traders
+            .iter()
+            .filter(|(other, _, _, ctrl, ..)| *other != trader && !ctrl.is_sleeping)
+            .map(|(other, _, other_transform, ..)| {
+                let distance = other_transform.translation.distance(transform.translation);
+                (other, distance)
+            })
+            .filter(|(_, distance)| *distance <= TRADE_RANGE)
+            .filter_map(|(other, distance)| {
+                let deal = self
+                    .between(trader, other)
+                    .or_else(|| self.between(other, trader))?;
+                Some((other, deal, distance))
+            })
+            .min_by(|(_, _, a), (_, _, b)| f32::total_cmp(a, b))
+            .map(|(other, deal, _)| (other, deal))
+    }
+}
+
+/// Trading 🤝 with a character nearby, both sides have to be trading with each other.
+#[derive(Component, Clone, Default, ActionSpawn)]
+pub struct Trade {
+    partner: Option<Entity>,
+    waited: f32,
+}
+
+pub fn trade_action(
+    time: Res<Time<Virtual>>,
+    mut query: Query<(Entity, ActionQuery, &mut Trade)>,
+    mut pending: Local<HashMap<Entity, [Entity; 2]>>,
+
+    deals: Deals,
+    mut transactions: EventWriter<ContainerTransaction>,
+    mut results: EventReader<TransactionResult>,
+) {
+    // a settled deal ends the actions of both sides
+    for TransactionResult { requester, result } in results.read() {
+        let Some(sides) = pending.remove(requester) else {
+            continue;
+        };
+
+        for entity in sides {
+            let Ok((_, mut action, _)) = query.get_mut(entity) else {
+                continue;
+            };
+            if !action.is_executing() {
+                continue;
+            }
+            match result {
+                Ok(()) => {
+                    debug!("Traded!");
+                    action.success();
+                }
+                Err(err) => {
+                    debug!("Trade failed: {}", err);
+                    action.failure();
+                }
+            }
+        }
+    }
+
+    let settling: HashSet<Entity> = pending.values().flatten().copied().collect();
+
+    // who is ready to trade with whom
+    let mut ready = HashMap::new();
+    for (entity, mut action, mut trade) in &mut query {
+        if action.is_cancelled() {
+            debug!("Trade was interrupted.");
+            action.failure();
+            continue;
+        }
+
+        if !action.is_executing() || settling.contains(&entity) {
+            continue;
+        }
+
+        let Some(partner) = trade
+            .partner
+            .or_else(|| deals.nearby(action.actor()).map(|(partner, _)| partner))
+        else {
+            debug!("No one to trade with!");
+            action.failure();
+            continue;
+        };
+        trade.partner = Some(partner);
+
+        trade.waited += time.delta_seconds();
+        if trade.waited > TRADE_PATIENCE {
+            debug!("Trade partner never came.");
+            action.failure();
+            continue;
+        }
+
+        ready.insert(action.actor(), (entity, partner));
+    }
+
+    for (&actor, &(entity, partner)) in &ready {
+        // each pair once, when both sides are trading with each other
+        let Some(&(other, back)) = ready.get(&partner) else {
+            continue;
+        };
+        if back != actor || actor > partner {
+            continue;
+        }
+
+        let Some(deal) = deals
+            .between(actor, partner)
+            .or_else(|| deals.between(partner, actor))
+        else {
+            debug!("The deal fell through.");
+            for side in [entity, other] {
+                if let Ok((_, mut action, _)) = query.get_mut(side) {
+                    action.failure();
+                }
+            }
+            continue;
+        };
+
+        debug!(
+            "{:?} trades {:?} for {:?} with {:?}",
+            deal.taker, deal.payment, deal.wanted, deal.giver
+        );
+        transactions.send(deal.transaction(entity));
+        pending.insert(entity, [entity, other]);
+    }
+}
+
+/// Wants to trade once a character nearby has a deal to make.
+#[derive(Component, Clone, Default, ScorerSpawn)]
+pub struct TradeScorer;
+
+pub fn trade_scorer(mut query: Query<ScorerQuery, With<TradeScorer>>, deals: Deals) {
+    for mut score in &mut query {
+        let awake = deals
+            .traders
+            .get(score.actor())
+            .is_ok_and(|(_, _, _, ctrl, ..)| !ctrl.is_sleeping);
+        let has_deal = awake && deals.nearby(score.actor()).is_some();
+
+        // ahead of walking to the market for it
+        score.set(if has_deal { 0.85 } else { 0.0 });
+    }
+}
@@ -0,0 +1,263 @@
+use super::item::{
+    BaseValue, Consumable, ContainerIndex, ContainerTransaction, ItemFilter, ReadContainer,
+    TransactionResult,
+};
+use crate::{
+    character::{CharacterController, Inventory},
+    game_state::GameState,
+    loading::ItemDatabase,
+};
+use bevy::{ecs::system::lifetimeless::Read, prelude::*, utils::HashMap};
+use std::collections::VecDeque;
+
+/// How many days [`TreasuryLedger`] keeps.
+pub const LEDGER_LENGTH: usize = 30;
+
+/// Holds the `money` the town collects in taxes.
+#[derive(Component, Reflect)]
+pub struct Treasury {
+    pub container: Entity,
+}
+
+/// Tax rates of the town.
+#[derive(Resource, Clone, Debug)]
+pub struct TaxConfig {
+    /// Part of the price of every market trade going to the treasury, paid by the seller.
+    pub sales_tax: f32,
+    /// Part of the value of what characters carry, collected once a day.
+    pub property_tax: f32,
+    /// Seconds of game time in a day.
+    pub day_length: f32,
+}
+
+impl Default for TaxConfig {
+    fn default() -> Self {
+        Self {
+            sales_tax: 0.1,
+            property_tax: 0.02,
+            day_length: 60.0,
+        }
+    }
+}
+
+impl TaxConfig {
+    pub fn day(&self, seconds: f32) -> u32 {
+        (seconds / self.day_length) as u32
+    }
+}
+
+/// Taxes collected over a day.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TaxIncome {
+    pub sales: f32,
+    pub property: f32,
+}
+
+impl TaxIncome {
+    pub fn total(&self) -> f32 {
+        self.sales + self.property
+    }
+}
+
+/// Income of a past day and the balance at its end.
+#[derive(Clone, Copy, Debug)]
+pub struct DayReport {
+    pub day: u32,
+    pub income: TaxIncome,
+    pub balance: f32,
+}
+
+/// Income of the current day and reports of the past ones, oldest first.
+#[derive(Resource, Default, Debug)]
+pub struct TreasuryLedger {
+    pub day: u32,
+    pub today: TaxIncome,
+    days: VecDeque<DayReport>,
+}
+
+impl TreasuryLedger {
+    pub fn days(&self) -> impl DoubleEndedIterator<Item = &DayReport> {
+        self.days.iter()
+    }
+
+    pub fn record_sales(&mut self, amount: f32) {
+        self.today.sales += amount;
+    }
+
+    /// Books property tax collected for `day`, which may already be closed.
+    pub fn record_property(&mut self, day: u32, amount: f32) {
+        if day == self.day {
+            self.today.property += amount;
+        }
+        for report in &mut self.days {
+            if report.day == day {
+                report.income.property += amount;
+            }
+            // days closed since then did not count it in their balance yet
+            if report.day >= day {
+                report.balance += amount;
+            }
+        }
+    }
+
+    /// Closes the current day with the balance at its end and starts the next one.
+    fn close_day(&mut self, balance: f32) {
+        if self.days.len() >= LEDGER_LENGTH {
+            self.days.pop_front();
+        }
+        self.days.push_back(DayReport {
+            day: self.day,
+            income: std::mem::take(&mut self.today),
+            balance,
+        });
+        self.day += 1;
+    }
+}
+
+pub struct TreasuryPlugin;
+
+impl Plugin for TreasuryPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Treasury>()
+            .init_resource::<TaxConfig>()
+            .init_resource::<TreasuryLedger>()
+            .add_systems(
+                Update,
+                collect_property_tax.run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+pub type PropertyQuery = (Option<Read<Consumable>>, Read<BaseValue>);
+
+/// Closes the day in the [`TreasuryLedger`] and taxes what characters carry.
+///
+/// Characters pay what they can of [`TaxConfig::property_tax`] for each day that ended,
+/// the money itself is not taxed. The tax is booked under the day it was collected for.
+#[allow(clippy::too_many_arguments)]
+pub fn collect_property_tax(
+    time: Res<Time<Virtual>>,
+    config: Res<TaxConfig>,
+    items: Res<ItemDatabase>,
+    treasury: Query<&Treasury>,
+    owners: Query<&Inventory, With<CharacterController>>,
+    property: ReadContainer<PropertyQuery>,
+    indices: Query<&ContainerIndex>,
+    mut ledger: ResMut<TreasuryLedger>,
+    mut pending: Local<HashMap<Entity, VecDeque<(u32, f32)>>>,
+    mut transactions: EventWriter<ContainerTransaction>,
+    mut results: EventReader<TransactionResult>,
+) {
+    // results come in the order the transactions were sent
+    for TransactionResult { requester, result } in results.read() {
+        let Some((day, tax)) = pending.get_mut(requester).and_then(VecDeque::pop_front) else {
+            continue;
+        };
+        match result {
+            Ok(()) => ledger.record_property(day, tax),
+            Err(err) => warn!("Collecting property tax failed: {}", err),
+        }
+    }
+    pending.retain(|_, taxes| !taxes.is_empty());
+
+    let day = config.day(time.elapsed_seconds());
+    if day <= ledger.day {
+        return;
+    }
+
+    let Some(money) = items.get("money") else {
+        return;
+    };
+    let Ok(treasury) = treasury.get_single() else {
+        return;
+    };
+    let total = |container| {
+        indices
+            .get(container)
+            .map_or(0.0, |index| index.total(money))
+    };
+
+    let owners: Vec<(Entity, f32)> = owners
+        .iter()
+        .map(|inventory| {
+            let value: f32 = property
+                .iter_recursive(ItemFilter::component::<BaseValue>(), inventory.container)
+                .map(|(_, (stack, BaseValue(base)))| {
+                    base * stack.map_or(1.0, |stack| stack.current)
+                })
+                .sum();
+            (inventory.container, value)
+        })
+        .collect();
+    let mut paid: HashMap<Entity, f32> = HashMap::new();
+
+    // every day that ended since the last run, a long frame may end several
+    while ledger.day < day {
+        let closing = ledger.day;
+
+        for &(container, value) in &owners {
+            let paid = paid.entry(container).or_default();
+            let tax = (value * config.property_tax).min(total(container) - *paid);
+            if tax <= 0.0 {
+                continue;
+            }
+            *paid += tax;
+
+            // the inventory container stands in for the owner, whose entity other systems use
+            debug!(
+                "Taxing {} of property worth {} for day {}",
+                tax, value, closing
+            );
+            transactions.send(ContainerTransaction::new(container).with_move(
+                money,
+                tax,
+                container,
+                treasury.container,
+            ));
+            pending
+                .entry(container)
+                .or_default()
+                .push_back((closing, tax));
+        }
+
+        ledger.close_day(total(treasury.container));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn day_counts_whole_days() {
+        let config = TaxConfig {
+            day_length: 60.0,
+            ..default()
+        };
+
+        assert_eq!(config.day(0.0), 0);
+        assert_eq!(config.day(59.9), 0);
+        assert_eq!(config.day(60.0), 1);
+        assert_eq!(config.day(150.0), 2);
+    }
+
+    #[test]
+    fn property_tax_is_booked_under_its_day() {
+        let mut ledger = TreasuryLedger::default();
+        ledger.record_sales(1.0);
+        ledger.close_day(10.0);
+        ledger.close_day(10.0);
+
+        // collected for the first day, arriving after the second one closed
+        ledger.record_property(0, 2.0);
+
+        let days: Vec<_> = ledger.days().collect();
+        assert_eq!(days[0].income.sales, 1.0);
+        assert_eq!(days[0].income.property, 2.0);
+        assert_eq!(days[0].balance, 12.0);
+        assert_eq!(days[1].income.property, 0.0);
+        assert_eq!(days[1].balance, 12.0);
+        assert_eq!(ledger.day, 2);
+        assert_eq!(ledger.today.property, 0.0);
+    }
+}
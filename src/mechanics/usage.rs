@@ -0,0 +1,268 @@
+use super::item::{
+    Consumable, ItemAsset, ItemFilter, ReadContainer, RestoreFatigue, RestoreHunger, SpeedBuff,
+};
+use super::Fatigue;
+use crate::{
+    character::{CharacterController, Inventory},
+    game_state::GameState,
+};
+use bevy::{ecs::system::lifetimeless::Read, prelude::*};
+use big_brain::prelude::*;
+
+/// Fatigue above which characters reach for something restoring it.
+pub const REMEDY_THRESHOLD: f32 = 70.0;
+/// Hunger above which characters eat.
+pub const HUNGER_THRESHOLD: f32 = 60.0;
+
+pub struct UsagePlugin;
+
+impl Plugin for UsagePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<UseItem>()
+            .add_systems(Update, (use_items, expire_buffs).chain())
+            .add_systems(Update, hunger_system)
+            .add_systems(
+                PreUpdate,
+                (
+                    Use::<RestoreFatigue>::system.in_set(BigBrainSet::Actions),
+                    Use::<RestoreHunger>::system.in_set(BigBrainSet::Actions),
+                    (remedy_scorer, eat_scorer).in_set(BigBrainSet::Scorers),
+                )
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+/// Uses up one unit of an item from the user's inventory and applies its effects.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct UseItem {
+    pub item: Entity,
+    pub user: Entity,
+}
+
+/// Appetite 🍞
+#[derive(Component)]
+pub struct Hunger {
+    pub change: f32,
+    pub current: f32,
+}
+
+pub fn hunger_system(time: Res<Time<Virtual>>, mut parameters: Query<&mut Hunger>) {
+    for mut param in &mut parameters {
+        param.current = (param.current + param.change * time.delta_seconds()).clamp(0.0, 100.0);
+    }
+}
+
+/// A [`SpeedBuff`] which is still running.
+#[derive(Clone, Copy, Debug)]
+pub struct ActiveBuff {
+    pub multiplier: f32,
+    /// Seconds until the buff wears off.
+    pub remaining: f32,
+}
+
+/// Buffs currently applied to [`CharacterController::speed`].
+#[derive(Component, Clone, Debug, Default)]
+pub struct SpeedBuffs(pub Vec<ActiveBuff>);
+
+pub type ItemUserQuery<'a> = (
+    &'a Inventory,
+    &'a mut CharacterController,
+    &'a mut SpeedBuffs,
+    Option<&'a mut Fatigue>,
+    Option<&'a mut Hunger>,
+);
+
+pub type UsableItemQuery<'a> = (
+    &'a Parent,
+    Option<&'a mut Consumable>,
+    Option<&'a RestoreFatigue>,
+    Option<&'a RestoreHunger>,
+    Option<&'a SpeedBuff>,
+);
+
+pub fn use_items(
+    mut commands: Commands,
+    mut events: ResMut<Events<UseItem>>,
+    mut users: Query<ItemUserQuery>,
+    mut items: Query<UsableItemQuery, With<Handle<ItemAsset>>>,
+    parents: Query<&Parent>,
+) {
+    for UseItem { item, user } in events.drain() {
+        let Ok((inventory, mut ctrl, mut buffs, fatigue, hunger)) = users.get_mut(user) else {
+            warn!("use of {:?} by {:?} which is not a character", item, user);
+            continue;
+        };
+
+        let Ok((parent, consumable, restore, feed, speed)) = items.get_mut(item) else {
+            warn!("use of {:?} which is not an item", item);
+            continue;
+        };
+
+        // anywhere in the inventory, bags included
+        let container = parent.get();
+        let carried = container == inventory.container
+            || parents
+                .iter_ancestors(container)
+                .any(|ancestor| ancestor == inventory.container);
+        if !carried {
+            warn!(
+                "use of {:?} which is not in the inventory of {:?}",
+                item, user
+            );
+            continue;
+        }
+
+        if restore.is_none() && feed.is_none() && speed.is_none() {
+            debug!("{:?} has no use", item);
+            continue;
+        }
+
+        // items without an amount are used up at once
+        let used_up = match consumable {
+            Some(mut consumable) => {
+                if consumable.current < 1.0 {
+                    debug!("{:?} is used up", item);
+                    continue;
+                }
+                consumable.take_amount(1.0);
+                consumable.is_empty()
+            }
+            None => true,
+        };
+
+        if used_up {
+            commands.entity(item).despawn_recursive();
+        }
+
+        if let (Some(RestoreFatigue(amount)), Some(mut fatigue)) = (restore, fatigue) {
+            fatigue.current = (fatigue.current - amount).clamp(0.0, 100.0);
+        }
+
+        if let (Some(RestoreHunger(amount)), Some(mut hunger)) = (feed, hunger) {
+            hunger.current = (hunger.current - amount).clamp(0.0, 100.0);
+        }
+
+        // dividing by the multiplier has to undo it once the buff runs out
+        if let Some(buff) = speed.filter(|buff| buff.multiplier > 0.0) {
+            ctrl.speed *= buff.multiplier;
+            buffs.0.push(ActiveBuff {
+                multiplier: buff.multiplier,
+                remaining: buff.duration,
+            });
+        }
+    }
+}
+
+pub fn expire_buffs(
+    time: Res<Time<Virtual>>,
+    mut query: Query<(&mut CharacterController, &mut SpeedBuffs)>,
+) {
+    for (mut ctrl, mut buffs) in &mut query {
+        buffs.0.retain_mut(|buff| {
+            buff.remaining -= time.delta_seconds();
+            if buff.remaining > 0.0 {
+                return true;
+            }
+
+            ctrl.speed /= buff.multiplier;
+            false
+        });
+    }
+}
+
+pub type UsableQuery = (Entity, Option<Read<Consumable>>);
+
+/// First item with effect `T` anywhere in the container which has a unit left to use.
+pub fn find_usable<T: Component>(
+    items: &ReadContainer<UsableQuery>,
+    container: Entity,
+) -> Option<Entity> {
+    items
+        .iter_recursive(ItemFilter::component::<T>(), container)
+        .find(|(_, (_, stack))| stack.is_none_or(|stack| stack.current >= 1.0))
+        .map(|(_, (item, _))| item)
+}
+
+/// Using an item with effect `T` 🧪
+#[derive(Component, Clone, ActionSpawn)]
+pub struct Use<T: Component + Clone> {
+    marker: std::marker::PhantomData<T>,
+}
+
+impl<T: Component + Clone> Default for Use<T> {
+    fn default() -> Self {
+        Self {
+            marker: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<T: Component + Clone> Use<T> {
+    pub fn system(
+        actors: Query<&Inventory>,
+        mut query: Query<ActionQuery, With<Self>>,
+        items: ReadContainer<UsableQuery>,
+        mut events: EventWriter<UseItem>,
+    ) {
+        for mut action in &mut query {
+            let inventory = actors.get(action.actor()).unwrap();
+
+            if action.is_executing() {
+                if let Some(item) = find_usable::<T>(&items, inventory.container) {
+                    debug!("Used {:?}!", item);
+                    events.send(UseItem {
+                        item,
+                        user: action.actor(),
+                    });
+                    action.success();
+                } else {
+                    debug!("Nothing to use.");
+                    action.failure();
+                }
+            }
+
+            if action.is_cancelled() {
+                action.failure();
+            }
+        }
+    }
+}
+
+/// Wants an item restoring fatigue once tired enough, instead of going to sleep.
+#[derive(Component, Clone, Default, ScorerSpawn)]
+pub struct RemedyScorer;
+
+pub fn remedy_scorer(
+    actors: Query<(&Inventory, &Fatigue, &CharacterController)>,
+    mut query: Query<ScorerQuery, With<RemedyScorer>>,
+    items: ReadContainer<UsableQuery>,
+) {
+    for mut score in &mut query {
+        let (inventory, fatigue, ctrl) = actors.get(score.actor()).expect("actor");
+
+        let needed = fatigue.current >= REMEDY_THRESHOLD && !ctrl.is_sleeping;
+        let has_remedy = find_usable::<RestoreFatigue>(&items, inventory.container).is_some();
+
+        score.set(if needed && has_remedy { 1.0 } else { 0.0 });
+    }
+}
+
+/// Wants something to eat once hungry enough.
+#[derive(Component, Clone, Default, ScorerSpawn)]
+pub struct EatScorer;
+
+pub fn eat_scorer(
+    actors: Query<(&Inventory, &Hunger, &CharacterController)>,
+    mut query: Query<ScorerQuery, With<EatScorer>>,
+    items: ReadContainer<UsableQuery>,
+) {
+    for mut score in &mut query {
+        let (inventory, hunger, ctrl) = actors.get(score.actor()).expect("actor");
+
+        let needed = hunger.current >= HUNGER_THRESHOLD && !ctrl.is_sleeping;
+        let has_food = find_usable::<RestoreHunger>(&items, inventory.container).is_some();
+
+        score.set(if needed && has_food { 1.0 } else { 0.0 });
+    }
+}
@@ -2,7 +2,9 @@ use crate::{game_state::GameState, raycast::PlaneRaycast};
 use bevy::prelude::*;
 
 pub mod inventory;
+pub mod market;
 pub mod time;
+pub mod treasury;
 
 pub struct PlayerPlugin;
 
@@ -19,7 +21,12 @@ impl Plugin for PlayerPlugin {
 
         app.add_systems(
             Update,
-            (self::inventory::inventory_ui, self::time::time_ui)
+            (
+                self::inventory::inventory_ui,
+                self::market::market_ui,
+                self::time::time_ui,
+                self::treasury::treasury_ui,
+            )
                 .run_if(in_state(GameState::Playing)),
         );
     }
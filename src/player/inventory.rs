@@ -1,23 +1,46 @@
 use crate::{
     character::Inventory,
-    mechanics::item::{Consumable, ItemName},
+    mechanics::{
+        item::{
+            Consumable, Container, Durability, ItemName, Perishable, RestoreFatigue, RestoreHunger,
+            SpeedBuff,
+        },
+        DropItem, ItemAsset, UseItem,
+    },
 };
 use bevy::prelude::*;
 use bevy_egui::{egui, EguiContexts};
 use big_brain::prelude::*;
 
+pub type InventoryItemQuery<'a> = (
+    &'a ItemName,
+    Option<&'a Consumable>,
+    Option<&'a Perishable>,
+    Option<&'a Durability>,
+    Has<RestoreFatigue>,
+    Has<RestoreHunger>,
+    Has<SpeedBuff>,
+);
+
+#[allow(clippy::too_many_arguments)]
 pub fn inventory_ui(
     mut contexts: EguiContexts,
-    query: Query<(&HasThinker, &Inventory), With<crate::player::Player>>,
+    query: Query<(Entity, &HasThinker, &Inventory, &Transform), With<crate::player::Player>>,
     children_query: Query<&Children>,
 
     work_need_query: Query<&Score, With<crate::mechanics::WorkNeedScorer>>,
     sell_need_query: Query<&Score, With<crate::mechanics::SellNeedScorer>>,
+    buy_need_query: Query<&Score, With<crate::mechanics::BuyNeedScorer>>,
     fatigue_query: Query<&Score, With<crate::mechanics::FatigueScorer>>,
 
-    items: Query<(&ItemName, Option<&Consumable>)>,
+    items: Query<InventoryItemQuery>,
+    containers: Query<(), With<Container>>,
+    handles: Query<&Handle<ItemAsset>>,
+    assets: Res<Assets<ItemAsset>>,
+    mut drop: EventWriter<DropItem>,
+    mut use_item: EventWriter<UseItem>,
 ) {
-    let Ok((thinker, inventory)) = query.get_single() else {
+    let Ok((player, thinker, inventory, transform)) = query.get_single() else {
         return;
     };
 
@@ -37,14 +60,46 @@ pub fn inventory_ui(
 
     let mut work_need = 0.0;
     let mut sell_need = 0.0;
+    let mut buy_need = 0.0;
     let mut fatigue = 0.0;
 
     for entity in children_query.iter_descendants(thinker.entity()) {
         work_need = read_score(work_need, entity, &work_need_query);
         sell_need = read_score(sell_need, entity, &sell_need_query);
+        buy_need = read_score(buy_need, entity, &buy_need_query);
         fatigue = read_score(fatigue, entity, &fatigue_query);
     }
 
+    // depth-first, the contents of a bag follow right after it
+    let mut rows = Vec::new();
+    let mut stack: Vec<_> = container_children
+        .iter()
+        .enumerate()
+        .rev()
+        .map(|(index, &item)| (item, inventory.container, (index + 1).to_string()))
+        .collect();
+
+    while let Some((item, container, num)) = stack.pop() {
+        if containers.contains(item) {
+            if let Ok(children) = children_query.get(item) {
+                stack.extend(
+                    children
+                        .iter()
+                        .enumerate()
+                        .rev()
+                        .map(|(index, &child)| (child, item, format!("{}.{}", num, index + 1))),
+                );
+            }
+        }
+        let icon = handles
+            .get(item)
+            .ok()
+            .and_then(|handle| assets.get(handle)?.icon.clone())
+            .map(|icon| contexts.add_image(icon));
+
+        rows.push((item, container, num, icon));
+    }
+
     egui::Area::new("#INVENTORY_HUD")
         .anchor(egui::Align2::LEFT_TOP, [12.0, 12.0])
         .show(contexts.ctx_mut(), |ui| {
@@ -55,10 +110,20 @@ pub fn inventory_ui(
                     ui.heading("Inventory");
 
                     ui.group(|ui| {
-                        for (index, &child) in container_children.iter().enumerate() {
-                            let num = index + 1;
+                        for (child, container, num, icon) in &rows {
+                            let (child, container) = (*child, *container);
 
-                            if let Ok((ItemName(name), consumable)) = items.get(child) {
+                            if let Ok((
+                                ItemName(name),
+                                consumable,
+                                perishable,
+                                durability,
+                                restores_fatigue,
+                                restores_hunger,
+                                speeds_up,
+                            )) = items.get(child)
+                            {
+                                let depth = num.matches('.').count();
                                 let mut text = format!("{:>3}: {}", num, name);
 
                                 if let Some(Consumable { current, maximum }) = consumable {
@@ -69,7 +134,41 @@ pub fn inventory_ui(
                                     }
                                 }
 
-                                ui.label(text);
+                                if let Some(Perishable { freshness, .. }) = perishable {
+                                    text.push_str(&format!(" fresh {:.0}%", freshness * 100.0));
+                                }
+
+                                if let Some(Durability {
+                                    current, maximum, ..
+                                }) = durability
+                                {
+                                    text.push_str(&format!(
+                                        " durability {:.0}/{:.0}",
+                                        current, maximum
+                                    ));
+                                }
+
+                                ui.horizontal(|ui| {
+                                    ui.add_space(depth as f32 * 12.0);
+                                    if let Some(icon) = *icon {
+                                        ui.image(egui::load::SizedTexture::new(icon, [16.0; 2]));
+                                    }
+                                    ui.label(text);
+                                    let usable = restores_fatigue || restores_hunger || speeds_up;
+                                    if usable && ui.small_button("use").clicked() {
+                                        use_item.send(UseItem {
+                                            item: child,
+                                            user: player,
+                                        });
+                                    }
+                                    if ui.small_button("drop").clicked() {
+                                        drop.send(DropItem {
+                                            item: child,
+                                            from: container,
+                                            position: transform.translation,
+                                        });
+                                    }
+                                });
                             }
                         }
                     });
@@ -77,6 +176,7 @@ pub fn inventory_ui(
                     ui.heading("Scores:");
                     ui.label(format!("work_need: {work_need:?}"));
                     ui.label(format!("sell_need: {sell_need:?}"));
+                    ui.label(format!("buy_need: {buy_need:?}"));
                     ui.label(format!("fatigue: {fatigue:?}"));
                 });
             });
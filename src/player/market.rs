@@ -0,0 +1,91 @@
+use super::CurrentlySelected;
+use crate::{
+    loading::ItemDatabase,
+    mechanics::{
+        item::{BaseValue, ItemName},
+        market::{Market, MarketGoods, OrderBook, PricingConfig, Side, StockRules},
+        ItemAsset,
+    },
+};
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+/// Stock, prices and open orders of the selected market.
+pub fn market_ui(
+    mut contexts: EguiContexts,
+    mut selected: ResMut<CurrentlySelected>,
+    markets: Query<(Entity, &MarketGoods, &OrderBook), With<Market>>,
+    items: Res<ItemDatabase>,
+    assets: Res<Assets<ItemAsset>>,
+    config: Res<PricingConfig>,
+    rules: Res<StockRules>,
+) {
+    let Ok((market, goods, book)) = markets.get(selected.selected) else {
+        return;
+    };
+
+    let mut rows = Vec::new();
+    for (id, handle) in items.iter() {
+        let Some(asset) = assets.get(handle) else {
+            continue;
+        };
+        let stock = goods.get(handle);
+        let Some(BaseValue(base)) = asset.get() else {
+            continue;
+        };
+
+        let name = asset.get().map_or(id.to_string(), |ItemName(name)| name);
+        let icon = asset.icon.clone().map(|icon| contexts.add_image(icon));
+        let limit = rules.limit(&items, handle);
+        let last = book.last_price(handle);
+        // orders of the market itself are its prices
+        let (bids, asks) = book
+            .orders()
+            .filter(|order| order.item == *handle && order.trader != market)
+            .fold((0, 0), |(bids, asks), order| match order.side {
+                Side::Bid => (bids + 1, asks),
+                Side::Ask => (bids, asks + 1),
+            });
+        rows.push((name, icon, stock, limit, base, last, bids, asks));
+    }
+
+    let mut open = true;
+    egui::Window::new("Market")
+        .anchor(egui::Align2::RIGHT_BOTTOM, [-12.0, -12.0])
+        .resizable(false)
+        .collapsible(false)
+        .open(&mut open)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::Grid::new("#MARKET_STOCK")
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("");
+                    ui.label("item");
+                    ui.label("stock");
+                    ui.label("sells for");
+                    ui.label("buys for");
+                    ui.label("last trade");
+                    ui.label("bids/asks");
+                    ui.end_row();
+
+                    for (name, icon, stock, limit, base, last, bids, asks) in &rows {
+                        if let Some(icon) = *icon {
+                            ui.image(egui::load::SizedTexture::new(icon, [16.0; 2]));
+                        } else {
+                            ui.label("");
+                        }
+                        ui.label(name);
+                        ui.label(format!("{:.0}/{:.0}", stock.stock, limit));
+                        ui.label(format!("{:.2}", config.buy_price(stock, *base)));
+                        ui.label(format!("{:.2}", config.sell_price(stock, *base)));
+                        ui.label(last.map_or("-".to_string(), |price| format!("{:.2}", price)));
+                        ui.label(format!("{}/{}", bids, asks));
+                        ui.end_row();
+                    }
+                });
+        });
+
+    if !open {
+        selected.selected = Entity::PLACEHOLDER;
+    }
+}
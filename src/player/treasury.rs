@@ -0,0 +1,71 @@
+use crate::{
+    loading::ItemDatabase,
+    mechanics::{
+        item::ContainerIndex,
+        treasury::{TaxConfig, Treasury, TreasuryLedger},
+    },
+};
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+/// Balance of the treasury, tax income of today and of the past days.
+pub fn treasury_ui(
+    mut contexts: EguiContexts,
+    treasury: Query<&Treasury>,
+    indices: Query<&ContainerIndex>,
+    items: Res<ItemDatabase>,
+    config: Res<TaxConfig>,
+    ledger: Res<TreasuryLedger>,
+) {
+    let Ok(treasury) = treasury.get_single() else {
+        return;
+    };
+    let balance = items.get("money").map_or(0.0, |money| {
+        indices
+            .get(treasury.container)
+            .map_or(0.0, |index| index.total(money))
+    });
+
+    egui::Window::new("Treasury")
+        .anchor(egui::Align2::LEFT_BOTTOM, [12.0, -12.0])
+        .resizable(false)
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(format!("balance: {:.2}", balance));
+            ui.label(format!(
+                "sales tax: {:.0}%, property tax: {:.0}% a day",
+                config.sales_tax * 100.0,
+                config.property_tax * 100.0
+            ));
+            ui.separator();
+
+            egui::Grid::new("#TREASURY_LEDGER")
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("day");
+                    ui.label("sales");
+                    ui.label("property");
+                    ui.label("income");
+                    ui.label("balance");
+                    ui.end_row();
+
+                    let today = &ledger.today;
+                    ui.label(format!("{} (today)", ledger.day + 1));
+                    ui.label(format!("{:.2}", today.sales));
+                    ui.label(format!("{:.2}", today.property));
+                    ui.label(format!("{:.2}", today.total()));
+                    ui.label(format!("{:.2}", balance));
+                    ui.end_row();
+
+                    // latest first
+                    for report in ledger.days().rev() {
+                        ui.label(format!("{}", report.day + 1));
+                        ui.label(format!("{:.2}", report.income.sales));
+                        ui.label(format!("{:.2}", report.income.property));
+                        ui.label(format!("{:.2}", report.income.total()));
+                        ui.label(format!("{:.2}", report.balance));
+                        ui.end_row();
+                    }
+                });
+        });
+}